@@ -30,6 +30,16 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
+    group.bench_function("streaming_quote_accept_time_to_vec", |b| {
+        b.iter(|| {
+            black_box(read_pcap_file(
+                black_box(&fixture),
+                PacketOrdering::StreamingQuoteAcceptTime,
+                Vec::new(),
+            ))
+        })
+    });
+
     group.bench_function("default_to_sink", |b| {
         b.iter(|| {
             black_box(read_pcap_file(
@@ -0,0 +1,553 @@
+//! Capture container detection and per-record validation for classic pcap
+//! and pcapng files.
+
+use crate::{
+    feed::{self, QUOTE_LEN},
+    net, pcapng, ParseError,
+};
+use std::ops::ControlFlow;
+
+// Packet layout constants for the feed's own framing:
+// 14 bytes Ethernet header
+// 20 bytes IPv4 header
+//  8 bytes UDP header
+const FRAME_TO_PAYLOAD: usize = 14 + 20 + 8;
+
+/// Shortest link + IP + UDP header stack we decode (raw IPv4, no link
+/// header) and a generous upper bound (Ethernet with stacked VLAN tags,
+/// IPv6 and extension headers).
+const MIN_FRAME_TO_PAYLOAD: usize = 20 + 8;
+const MAX_FRAME_TO_PAYLOAD: usize = 160;
+
+/// Classic pcap: 16 bytes per-packet record header before the frame.
+const PCAP_RECORD_HDR_LEN: usize = 16;
+pub(crate) const PCAP_GLOBAL_HDR_LEN: usize = 24;
+
+/// Most bytes of other messages searched for before a quote that shares
+/// its datagram: a standard Ethernet MTU's worth of UDP payload.
+const MAX_BATCH_LEN: usize = 1500 - 20 - 8;
+
+/// Upper bound on a plausible captured length when resynchronising.
+const MAX_RECORD_LEN: u32 = 256 * 1024;
+
+/// Consecutive well-formed records required to accept a resync candidate.
+const RESYNC_CHAIN: usize = 8;
+
+/// Records whose timestamps differ by more than this are not one chain.
+const RESYNC_MAX_TS_SPREAD: u32 = 86_400;
+
+/// Bytes below which `Capture::seek` stops bisecting and walks records.
+const SEEK_WALK_BYTES: usize = 64 * 1024;
+
+/// One captured packet found by walking record headers.
+pub(crate) struct Record<'a> {
+    /// Offset of the record (or block) header in the capture.
+    pub(crate) start: usize,
+    /// Offset of the frame's first byte in the capture.
+    pub(crate) frame_pos: usize,
+    pub(crate) linktype: u16,
+    pub(crate) ts_sec: u32,
+    pub(crate) ts_nsec: u32,
+    pub(crate) frame: &'a [u8],
+}
+
+/// The UDP payload of a record found by `Capture::enclosing_payload`.
+#[derive(Clone, Copy)]
+pub(crate) struct Payload {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) ts_sec: u32,
+    pub(crate) ts_nsec: u32,
+}
+
+/// What sits at a candidate record boundary.
+enum Block<'a> {
+    Packet(Record<'a>),
+    /// A well-formed block that carries no packet (pcapng metadata).
+    Other,
+    /// A pcapng packet on an interface with no description.
+    UnknownInterface(u32),
+    /// A pcapng packet on an interface whose link type we do not decode.
+    UnsupportedLinkType(u16),
+}
+
+enum Format {
+    /// `nanos` is set by the nanosecond magic: `ts_usec` then holds ns.
+    Pcap {
+        nanos: bool,
+        linktype: u16,
+    },
+    PcapNg(pcapng::Sections),
+}
+
+/// Rejects captures we could only ever print nothing for: those where
+/// every one of `linktypes`, each with its offset, is unsupported, naming
+/// the first. None at all is fine; a pcapng section may declare none.
+fn check_linktypes(linktypes: impl IntoIterator<Item = (u16, usize)>) -> Result<(), ParseError> {
+    let mut unsupported = None;
+    for (linktype, offset) in linktypes {
+        if net::is_supported_linktype(linktype) {
+            return Ok(());
+        }
+        unsupported.get_or_insert(ParseError::UnsupportedLinkType {
+            offset: offset as u64,
+            linktype,
+        });
+    }
+    unsupported.map_or(Ok(()), Err)
+}
+
+/// A memory-mapped capture's container format, detected from its first
+/// bytes.
+pub(crate) struct Capture {
+    le: bool,
+    format: Format,
+    data_start: usize,
+}
+
+impl Capture {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        if data.len() < 4 {
+            return Err(ParseError::TruncatedHeader { offset: 0 });
+        }
+
+        let (le, nanos) = match &data[0..4] {
+            [0x0a, 0x0d, 0x0d, 0x0a] => {
+                // Only the first section is known yet; packets on other
+                // unsupported interfaces are skipped one by one.
+                let sections = pcapng::Sections::parse(data)?;
+                let interfaces = &sections.first().interfaces;
+                check_linktypes(interfaces.iter().map(|i| (i.linktype, i.offset)))?;
+                return Ok(Self {
+                    le: sections.le(),
+                    data_start: sections.data_start(),
+                    format: Format::PcapNg(sections),
+                });
+            }
+            [0xd4, 0xc3, 0xb2, 0xa1] => (true, false),
+            [0x4d, 0x3c, 0xb2, 0xa1] => (true, true),
+            [0xa1, 0xb2, 0xc3, 0xd4] => (false, false),
+            [0xa1, 0xb2, 0x3c, 0x4d] => (false, true),
+            magic => {
+                let found = u32::from_be_bytes(magic.try_into().unwrap());
+                return Err(ParseError::BadMagic { offset: 0, found });
+            }
+        };
+        if data.len() < PCAP_GLOBAL_HDR_LEN {
+            return Err(ParseError::TruncatedHeader { offset: 0 });
+        }
+
+        let mut capture = Self {
+            le,
+            format: Format::Pcap { nanos, linktype: 0 },
+            data_start: PCAP_GLOBAL_HDR_LEN,
+        };
+        // The upper 16 bits of the network field carry FCS information.
+        let linktype = capture.u32_at(data, 20) as u16;
+        check_linktypes([(linktype, 20)])?;
+        capture.format = Format::Pcap { nanos, linktype };
+        Ok(capture)
+    }
+
+    /// First byte after the file header; packet data never starts earlier.
+    pub(crate) fn data_start(&self) -> usize {
+        self.data_start
+    }
+
+    /// Reads a 4-byte integer using the capture's endianness.
+    #[inline]
+    fn u32_at(&self, data: &[u8], j: usize) -> u32 {
+        let b: [u8; 4] = data[j..j + 4].try_into().unwrap();
+        if self.le {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        }
+    }
+
+    /// Checks that a `B6034` marker at `gpos` is the UDP payload of a quote
+    /// record and returns that record's `(ts_sec, ts_nsec)`.
+    ///
+    /// This covers the common case of one quote per datagram, where the
+    /// quote is the last thing in its frame, so the enclosing record's
+    /// captured length must end exactly at the payload's end. The feed's
+    /// own Ethernet + IPv4 + UDP framing is tried first with no further
+    /// decoding; other header lengths (VLAN tags, IP options, IPv6) are
+    /// only accepted once the decoded UDP payload starts at `gpos`.
+    #[inline]
+    pub(crate) fn quote_timestamp(&self, data: &[u8], gpos: usize) -> Option<(u32, u32)> {
+        if gpos + QUOTE_LEN > data.len() {
+            return None;
+        }
+        self.record_ending_at(data, gpos, FRAME_TO_PAYLOAD, false)
+            .or_else(|| {
+                (MIN_FRAME_TO_PAYLOAD..=MAX_FRAME_TO_PAYLOAD)
+                    .filter(|&hdr_len| hdr_len != FRAME_TO_PAYLOAD)
+                    .find_map(|hdr_len| self.record_ending_at(data, gpos, hdr_len, true))
+            })
+    }
+
+    /// Timestamp of the record whose frame has `hdr_len` header bytes before
+    /// a quote payload at `gpos`, if such a record exists. With `decode`
+    /// unset, only the feed's own Ethernet framing is trusted.
+    #[inline]
+    fn record_ending_at(
+        &self,
+        data: &[u8],
+        gpos: usize,
+        hdr_len: usize,
+        decode: bool,
+    ) -> Option<(u32, u32)> {
+        let frame_len = hdr_len + QUOTE_LEN;
+        let frame_pos = gpos.checked_sub(hdr_len)?;
+        let payload_starts_here = |linktype: u16| {
+            if !decode {
+                return linktype == net::LINKTYPE_ETHERNET;
+            }
+            let frame = &data[frame_pos..frame_pos + frame_len];
+            net::udp_datagram(linktype, frame).is_some_and(|d| d.offset == hdr_len)
+        };
+        match &self.format {
+            &Format::Pcap { nanos, linktype } => {
+                //To validate the pcap record, we subtract the header lengths,
+                //so this match must be far enough into the file
+                let rec = gpos.checked_sub(PCAP_RECORD_HDR_LEN + hdr_len)?;
+                if rec < self.data_start
+                    || self.u32_at(data, rec + 8) as usize != frame_len
+                    || !payload_starts_here(linktype)
+                {
+                    return None;
+                }
+                let frac = self.u32_at(data, rec + 4);
+                let ts_nsec = if nanos { frac } else { frac * 1000 };
+                Some((self.u32_at(data, rec), ts_nsec))
+            }
+            Format::PcapNg(sections) => {
+                let block = gpos.checked_sub(pcapng::EPB_HDR_LEN + hdr_len)?;
+                if block < self.data_start
+                    || self.u32_at(data, block) != pcapng::ENHANCED_PACKET
+                    || self.u32_at(data, block + 20) as usize != frame_len
+                {
+                    return None;
+                }
+                // The trailing copy of the block length must agree too.
+                let block_len = self.u32_at(data, block + 4) as usize;
+                let block_end = block.checked_add(block_len)?;
+                if block_len < pcapng::EPB_HDR_LEN + frame_len + 4
+                    || block_end > data.len()
+                    || self.u32_at(data, block_end - 4) as usize != block_len
+                {
+                    return None;
+                }
+                let interfaces = &sections.at(data, block).interfaces;
+                let iface = interfaces.get(self.u32_at(data, block + 8) as usize)?;
+                if !payload_starts_here(iface.linktype) {
+                    return None;
+                }
+                Some(iface.timestamp(self.u32_at(data, block + 12), self.u32_at(data, block + 16)))
+            }
+        }
+    }
+
+    /// Finds the record whose UDP payload carries a quote at `gpos` next to
+    /// other messages, for datagrams `quote_timestamp` rejects.
+    ///
+    /// The messages following `gpos` give the places the payload may end.
+    /// Candidate record headers are tried nearest first, and only one whose
+    /// frame ends on such a boundary is decoded; it must put a quote message
+    /// exactly at `gpos`.
+    pub(crate) fn enclosing_payload(&self, data: &[u8], gpos: usize) -> Option<Payload> {
+        let after = &data[gpos..(gpos + MAX_BATCH_LEN).min(data.len())];
+        let ends: Vec<usize> = feed::messages(after)
+            .map(|(offset, m)| gpos + offset + m.len())
+            .collect();
+        let (&first_end, &last_end) = (ends.first()?, ends.last()?);
+
+        let hdr_len = match self.format {
+            Format::Pcap { .. } => PCAP_RECORD_HDR_LEN,
+            Format::PcapNg(_) => pcapng::EPB_HDR_LEN,
+        };
+        // Both headers end with captured length + original length.
+        let cap_len_at = hdr_len - 8;
+        let lowest = gpos
+            .saturating_sub(hdr_len + MAX_FRAME_TO_PAYLOAD + MAX_BATCH_LEN)
+            .max(self.data_start);
+        let highest = gpos.checked_sub(hdr_len + MIN_FRAME_TO_PAYLOAD)?;
+
+        (lowest..=highest).rev().find_map(|rec| {
+            let frame_end = rec + hdr_len + self.u32_at(data, rec + cap_len_at) as usize;
+            if frame_end < first_end
+                || frame_end > last_end
+                || ends.binary_search(&frame_end).is_err()
+            {
+                return None;
+            }
+            let Some((Block::Packet(record), _)) = self.block_at(data, rec) else {
+                return None;
+            };
+            let dgram = net::udp_datagram(record.linktype, record.frame)?;
+            let start = record.frame_pos + dgram.offset;
+            feed::quotes(dgram.payload)
+                .any(|(offset, _)| start + offset == gpos)
+                .then_some(Payload {
+                    start,
+                    end: start + dgram.payload.len(),
+                    ts_sec: record.ts_sec,
+                    ts_nsec: record.ts_nsec,
+                })
+        })
+    }
+
+    /// Calls `f` for each packet record from the first boundary at or after
+    /// `base` until it breaks or the capture ends. Records starting at or
+    /// past `own_end` are still passed on, so a range can read ahead.
+    ///
+    /// A range that begins mid-file is first resynchronised onto the next
+    /// offset where `RESYNC_CHAIN` well-formed records follow each other.
+    /// Every range applies the same rule to its neighbour's `own_end`, so
+    /// each record starts in exactly one range. A malformed header inside
+    /// `[base, own_end)` is passed to `f` as an error and skipped the same
+    /// way; past it, the walk stops.
+    pub(crate) fn walk_records<'a>(
+        &self,
+        data: &'a [u8],
+        base: usize,
+        own_end: usize,
+        mut f: impl FnMut(Result<Record<'a>, ParseError>) -> ControlFlow<()>,
+    ) {
+        let mut pos = if base <= self.data_start {
+            Some(self.data_start)
+        } else {
+            self.resync(data, base, own_end)
+        };
+        while let Some(p) = pos {
+            let flow = match self.block_at(data, p) {
+                Some((Block::Packet(record), next)) => {
+                    pos = Some(next);
+                    f(Ok(record))
+                }
+                Some((Block::Other, next)) => {
+                    pos = Some(next);
+                    ControlFlow::Continue(())
+                }
+                Some((Block::UnknownInterface(interface), next)) => {
+                    pos = Some(next);
+                    if p < own_end {
+                        f(Err(ParseError::UnknownInterface {
+                            offset: p as u64,
+                            interface,
+                        }))
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                }
+                Some((Block::UnsupportedLinkType(linktype), next)) => {
+                    pos = Some(next);
+                    if p < own_end {
+                        f(Err(ParseError::UnsupportedLinkType {
+                            offset: p as u64,
+                            linktype,
+                        }))
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                }
+                None if p < own_end => {
+                    pos = self.resync(data, p + 1, own_end);
+                    f(Err(self.diagnose(data, p)))
+                }
+                None => return,
+            };
+            if flow.is_break() {
+                return;
+            }
+        }
+    }
+
+    /// Fails if part of `[from, to)` cannot be read at all: for pcapng, a
+    /// later section in the other byte order from the first, or one whose
+    /// leading blocks are malformed.
+    pub(crate) fn check_range(
+        &self,
+        data: &[u8],
+        from: usize,
+        to: usize,
+    ) -> Result<(), ParseError> {
+        match &self.format {
+            Format::Pcap { .. } => Ok(()),
+            Format::PcapNg(sections) => sections.check(data, from, to),
+        }
+    }
+
+    /// Offset of the first packet record whose timestamp `before` rejects,
+    /// or the capture's length if there is none, found by bisecting the
+    /// capture on record timestamps. Exact when records are in time order,
+    /// as in a capture written from one interface.
+    ///
+    /// Probes start near the start of the capture and double their reach
+    /// until one lands past the bound, so the search reads nothing much
+    /// more than twice as far into the capture as the record it finds:
+    /// pcapng sections are only looked for as far as a probe goes.
+    pub(crate) fn seek(&self, data: &[u8], before: impl Fn(u32, u32) -> bool) -> usize {
+        let (mut lo, mut hi) = (self.data_start, data.len());
+        let mut reach = SEEK_WALK_BYTES;
+        while hi - lo > SEEK_WALK_BYTES {
+            let mid = lo + ((hi - lo) / 2).min(reach);
+            let mut first = None;
+            self.walk_records(data, mid, hi, |record| match record {
+                Ok(record) => {
+                    first = Some(record);
+                    ControlFlow::Break(())
+                }
+                Err(_) => ControlFlow::Continue(()),
+            });
+            match first {
+                Some(r) if r.start < hi && before(r.ts_sec, r.ts_nsec) => {
+                    lo = r.start;
+                    reach = reach.saturating_mul(2);
+                }
+                _ => {
+                    hi = mid;
+                    reach = usize::MAX;
+                }
+            }
+        }
+        let mut found = data.len();
+        self.walk_records(data, lo, data.len(), |record| match record {
+            Ok(r) if !before(r.ts_sec, r.ts_nsec) => {
+                found = r.start;
+                ControlFlow::Break(())
+            }
+            _ => ControlFlow::Continue(()),
+        });
+        found
+    }
+
+    /// Why `block_at` rejected `pos`: a record whose header is fine but
+    /// whose data runs past the end of the file is truncated, anything
+    /// else is malformed.
+    fn diagnose(&self, data: &[u8], pos: usize) -> ParseError {
+        let offset = pos as u64;
+        let truncated = match self.format {
+            Format::Pcap { .. } => {
+                pos + PCAP_RECORD_HDR_LEN > data.len()
+                    || (self.u32_at(data, pos + 8) <= MAX_RECORD_LEN
+                        && pos + PCAP_RECORD_HDR_LEN + self.u32_at(data, pos + 8) as usize
+                            > data.len())
+            }
+            Format::PcapNg(_) => {
+                pos + 12 > data.len()
+                    || (pcapng::is_known_block_type(self.u32_at(data, pos))
+                        && self.u32_at(data, pos + 4) as usize > data.len() - pos)
+            }
+        };
+        if truncated {
+            ParseError::TruncatedRecord { offset }
+        } else {
+            ParseError::MalformedRecord { offset }
+        }
+    }
+
+    /// First offset in `[from, own_end)` that starts a chain of valid records.
+    fn resync(&self, data: &[u8], from: usize, own_end: usize) -> Option<usize> {
+        (from..own_end).find(|&p| self.chain_at(data, p))
+    }
+
+    fn chain_at(&self, data: &[u8], mut pos: usize) -> bool {
+        let mut first_ts = None;
+        for _ in 0..RESYNC_CHAIN {
+            if pos == data.len() {
+                return true;
+            }
+            let Some((block, next)) = self.block_at(data, pos) else {
+                return false;
+            };
+            if let Block::Packet(r) = block {
+                let first = *first_ts.get_or_insert(r.ts_sec);
+                if r.ts_sec.abs_diff(first) > RESYNC_MAX_TS_SPREAD {
+                    return false;
+                }
+            }
+            pos = next;
+        }
+        true
+    }
+
+    /// Decodes the record or block header at `pos`, returning it together
+    /// with the offset of the next one, or `None` if `pos` cannot start one.
+    #[inline]
+    fn block_at<'a>(&self, data: &'a [u8], pos: usize) -> Option<(Block<'a>, usize)> {
+        match &self.format {
+            &Format::Pcap { nanos, linktype } => {
+                if pos + 16 > data.len() {
+                    return None;
+                }
+                let ts_frac = self.u32_at(data, pos + 4);
+                let incl_len = self.u32_at(data, pos + 8);
+                let orig_len = self.u32_at(data, pos + 12);
+                let frac_limit = if nanos { 1_000_000_000 } else { 1_000_000 };
+                if incl_len > orig_len || incl_len > MAX_RECORD_LEN || ts_frac >= frac_limit {
+                    return None;
+                }
+                let frame_pos = pos + 16;
+                let next = frame_pos + incl_len as usize;
+                let frame = data.get(frame_pos..next)?;
+                let record = Record {
+                    start: pos,
+                    frame_pos,
+                    linktype,
+                    ts_sec: self.u32_at(data, pos),
+                    ts_nsec: if nanos { ts_frac } else { ts_frac * 1000 },
+                    frame,
+                };
+                Some((Block::Packet(record), next))
+            }
+            Format::PcapNg(sections) => {
+                if pos + 12 > data.len() {
+                    return None;
+                }
+                let block_type = self.u32_at(data, pos);
+                let block_len = self.u32_at(data, pos + 4) as usize;
+                if !pcapng::is_known_block_type(block_type)
+                    || block_len < 12
+                    || !block_len.is_multiple_of(4)
+                    || block_len > data.len() - pos
+                    || self.u32_at(data, pos + block_len - 4) as usize != block_len
+                {
+                    return None;
+                }
+                let next = pos + block_len;
+                if block_type != pcapng::ENHANCED_PACKET || block_len < pcapng::EPB_HDR_LEN + 4 {
+                    return Some((Block::Other, next));
+                }
+                let cap_len = self.u32_at(data, pos + 20) as usize;
+                let frame_pos = pos + pcapng::EPB_HDR_LEN;
+                if cap_len > block_len - pcapng::EPB_HDR_LEN - 4 {
+                    return None;
+                }
+                // Packets on interfaces we have no description for, or
+                // whose link type we do not decode, are skipped, but the
+                // block itself is well-formed.
+                let if_id = self.u32_at(data, pos + 8);
+                let interfaces = &sections.at(data, pos).interfaces;
+                let Some(iface) = interfaces.get(if_id as usize) else {
+                    return Some((Block::UnknownInterface(if_id), next));
+                };
+                if !net::is_supported_linktype(iface.linktype) {
+                    return Some((Block::UnsupportedLinkType(iface.linktype), next));
+                }
+                let (ts_sec, ts_nsec) =
+                    iface.timestamp(self.u32_at(data, pos + 12), self.u32_at(data, pos + 16));
+                let record = Record {
+                    start: pos,
+                    frame_pos,
+                    linktype: iface.linktype,
+                    ts_sec,
+                    ts_nsec,
+                    frame: &data[frame_pos..frame_pos + cap_len],
+                };
+                Some((Block::Packet(record), next))
+            }
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Columnar export: quotes as Arrow record batches, written as Arrow IPC or
+//! Parquet.
+//!
+//! The pipelines hand quotes over as fixed-size binary rows (see
+//! [`write_row`]) in their final order; the printer thread decodes them into
+//! column builders and writes a record batch at a time.
+
+use crate::{
+    feed::QUOTE_LEN, parse_digits, OutputFormat, ParseError, PriceDecimals, Quote, QuoteReader,
+    KST_OFFSET_SECS, LEVELS,
+};
+use arrow_array::{
+    builder::{
+        ArrayBuilder, Int64Builder, StringBuilder, StringDictionaryBuilder,
+        TimestampNanosecondBuilder, UInt8Builder,
+    },
+    types::Int32Type,
+    ArrayRef, RecordBatch,
+};
+use arrow_ipc::writer::{FileWriter, StreamWriter};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+#[cfg(feature = "parquet")]
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{
+    io::{self, Write},
+    sync::{mpsc, Arc},
+};
+
+/// Bytes per binary row: the packet time's `ts_sec` and `ts_nsec`,
+/// little-endian, then the quote message.
+pub(crate) const ROW_LEN: usize = 8 + QUOTE_LEN;
+
+/// Rows per record batch handed to the output writer.
+const BATCH_ROWS: usize = 64 * 1024;
+
+/// Timestamps are instants; they display in KST.
+const TIMEZONE: &str = "+09:00";
+
+/// Appends a quote as a binary row.
+#[inline]
+pub(crate) fn write_row(out: &mut Vec<u8>, ts_sec: u32, ts_nsec: u32, quote: Quote) {
+    out.extend_from_slice(&ts_sec.to_le_bytes());
+    out.extend_from_slice(&ts_nsec.to_le_bytes());
+    out.extend_from_slice(quote.as_bytes());
+}
+
+/// Names of the integer columns after `price_decimals`, in order.
+fn number_columns() -> Vec<String> {
+    let mut columns: Vec<String> = [
+        "total_bid_volume",
+        "bid_quotes",
+        "total_ask_volume",
+        "ask_quotes",
+    ]
+    .map(str::to_owned)
+    .into();
+    for side in ["bid", "ask"] {
+        for level in 1..=LEVELS {
+            columns.push(format!("{side}_price_{level}"));
+            columns.push(format!("{side}_quantity_{level}"));
+            columns.push(format!("{side}_quotes_{level}"));
+        }
+    }
+    columns
+}
+
+/// Schema of the quote record batches: one column per field, with levels
+/// numbered from 1, the best price, as in CSV output.
+///
+/// Times are nanoseconds since the epoch; the accept time takes the
+/// packet's date. `market_type` and `issue_code` are dictionary-encoded.
+/// Prices are integers in units of `10^-price_decimals`, the issue's
+/// implied decimals. Numeric fields that are not all digits are null.
+pub fn quote_schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Nanosecond, Some(TIMEZONE.into()));
+    let dictionary = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+    let mut fields = vec![
+        Field::new("packet_time", timestamp.clone(), false),
+        Field::new("accept_time", timestamp, true),
+        Field::new("market_type", dictionary.clone(), false),
+        Field::new("issue_code", dictionary, false),
+        Field::new("issue_seq_no", DataType::Int64, true),
+        Field::new("market_status", DataType::Utf8, false),
+        Field::new("price_decimals", DataType::UInt8, false),
+    ];
+    for name in number_columns() {
+        fields.push(Field::new(name, DataType::Int64, true));
+    }
+    Arc::new(Schema::new(fields))
+}
+
+/// Column builders for the rows of one batch.
+struct QuoteColumns {
+    schema: SchemaRef,
+    packet_time: TimestampNanosecondBuilder,
+    accept_time: TimestampNanosecondBuilder,
+    market_type: StringDictionaryBuilder<Int32Type>,
+    issue_code: StringDictionaryBuilder<Int32Type>,
+    issue_seq_no: Int64Builder,
+    market_status: StringBuilder,
+    price_decimals: UInt8Builder,
+    /// One per `number_columns` entry.
+    numbers: Vec<Int64Builder>,
+}
+
+impl QuoteColumns {
+    fn new() -> Self {
+        let timestamp = || TimestampNanosecondBuilder::new().with_timezone(TIMEZONE);
+        Self {
+            schema: quote_schema(),
+            packet_time: timestamp(),
+            accept_time: timestamp(),
+            market_type: StringDictionaryBuilder::new(),
+            issue_code: StringDictionaryBuilder::new(),
+            issue_seq_no: Int64Builder::new(),
+            market_status: StringBuilder::new(),
+            price_decimals: UInt8Builder::new(),
+            numbers: number_columns()
+                .iter()
+                .map(|_| Int64Builder::new())
+                .collect(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.packet_time.len()
+    }
+
+    /// Appends one binary row.
+    fn push_row(&mut self, row: &[u8], decimals: &PriceDecimals) {
+        let ts_sec = u32::from_le_bytes(row[0..4].try_into().unwrap());
+        let ts_nsec = u32::from_le_bytes(row[4..8].try_into().unwrap());
+        let quote = Quote::new(&row[8..]).expect("rows hold quotes");
+        self.push(ts_sec, ts_nsec, quote, decimals);
+    }
+
+    /// Appends a quote received at `(ts_sec, ts_nsec)`.
+    fn push(&mut self, ts_sec: u32, ts_nsec: u32, quote: Quote, decimals: &PriceDecimals) {
+        let number = |field: &[u8]| parse_digits(field).map(|n| n as i64);
+
+        let packet_ns = ts_sec as i64 * 1_000_000_000 + ts_nsec as i64;
+        self.packet_time.append_value(packet_ns);
+        let midnight =
+            (ts_sec as i64 + KST_OFFSET_SECS as i64) / 86_400 * 86_400 - KST_OFFSET_SECS as i64;
+        let accept = quote.accept_time();
+        let accept_ns = accept
+            .iter()
+            .all(u8::is_ascii_digit)
+            .then(|| (midnight * 100 + quote.accept_time_cs() as i64) * 10_000_000);
+        self.accept_time.append_option(accept_ns);
+
+        let mut market_type = [0; 4];
+        self.market_type
+            .append_value(char::from(quote.market_type()).encode_utf8(&mut market_type));
+        self.issue_code
+            .append_value(String::from_utf8_lossy(quote.issue_code()));
+        self.issue_seq_no
+            .append_option(number(quote.issue_seq_no()));
+        self.market_status
+            .append_value(String::from_utf8_lossy(quote.market_status()));
+        self.price_decimals
+            .append_value(decimals.for_issue(quote.issue_code()));
+
+        let mut numbers = self.numbers.iter_mut();
+        let mut push = |field: &[u8]| numbers.next().unwrap().append_option(number(field));
+        push(quote.total_bid_volume());
+        push(quote.bid_valid_quotes_total());
+        push(quote.total_ask_volume());
+        push(quote.ask_valid_quotes_total());
+        for (i, level) in quote.bids().enumerate() {
+            push(level.price);
+            push(level.quantity);
+            push(quote.bid_valid_quotes(i));
+        }
+        for (i, level) in quote.asks().enumerate() {
+            push(level.price);
+            push(level.quantity);
+            push(quote.ask_valid_quotes(i));
+        }
+    }
+
+    /// Takes the rows appended so far as a batch, leaving the builders
+    /// empty.
+    fn finish(&mut self) -> RecordBatch {
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.packet_time.finish()),
+            Arc::new(self.accept_time.finish()),
+            Arc::new(self.market_type.finish()),
+            Arc::new(self.issue_code.finish()),
+            Arc::new(self.issue_seq_no.finish()),
+            Arc::new(self.market_status.finish()),
+            Arc::new(self.price_decimals.finish()),
+        ];
+        for numbers in &mut self.numbers {
+            columns.push(Arc::new(numbers.finish()));
+        }
+        RecordBatch::try_new(self.schema.clone(), columns).expect("columns match the schema")
+    }
+}
+
+/// Quotes of one capture as Arrow record batches, in file order; see
+/// [`QuoteReader::record_batches`].
+pub struct QuoteBatches {
+    reader: QuoteReader,
+    decimals: PriceDecimals,
+    batch_rows: usize,
+    columns: QuoteColumns,
+    /// The reader's error, held back until the rows before it are yielded.
+    failed: Option<ParseError>,
+}
+
+impl QuoteBatches {
+    pub(crate) fn new(reader: QuoteReader, decimals: PriceDecimals, batch_rows: usize) -> Self {
+        Self {
+            reader,
+            decimals,
+            batch_rows: batch_rows.max(1),
+            columns: QuoteColumns::new(),
+            failed: None,
+        }
+    }
+
+    /// The schema of every batch, [`quote_schema`].
+    pub fn schema(&self) -> SchemaRef {
+        self.columns.schema.clone()
+    }
+}
+
+impl Iterator for QuoteBatches {
+    type Item = Result<RecordBatch, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.failed.is_none() && self.columns.len() < self.batch_rows {
+            match self.reader.next() {
+                Some(Ok(record)) => self.columns.push(
+                    record.ts_sec,
+                    record.ts_nsec,
+                    record.quote(),
+                    &self.decimals,
+                ),
+                Some(Err(e)) => self.failed = Some(e),
+                None => break,
+            }
+        }
+        if self.columns.len() > 0 {
+            return Some(Ok(self.columns.finish()));
+        }
+        self.failed.take().map(Err)
+    }
+}
+
+/// Where the printer thread writes record batches.
+enum BatchWriter<W: Write + Send> {
+    /// With the schema of the batches it takes, see `without_dictionaries`.
+    IpcFile(FileWriter<W>, SchemaRef),
+    IpcStream(StreamWriter<W>),
+    #[cfg(feature = "parquet")]
+    Parquet(ArrowWriter<W>),
+}
+
+impl<W: Write + Send> BatchWriter<W> {
+    fn new(format: OutputFormat, writer: W, schema: SchemaRef) -> io::Result<Self> {
+        Ok(match format {
+            OutputFormat::ArrowFile => {
+                let schema = Arc::new(without_dictionaries(&schema));
+                let file = FileWriter::try_new(writer, &schema).map_err(io::Error::other)?;
+                BatchWriter::IpcFile(file, schema)
+            }
+            OutputFormat::ArrowStream => BatchWriter::IpcStream(
+                StreamWriter::try_new(writer, &schema).map_err(io::Error::other)?,
+            ),
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                BatchWriter::Parquet(
+                    ArrowWriter::try_new(writer, schema, Some(properties))
+                        .map_err(io::Error::other)?,
+                )
+            }
+            _ => unreachable!("{format:?} is not a columnar format"),
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> io::Result<()> {
+        match self {
+            BatchWriter::IpcFile(file, schema) => {
+                let columns = batch
+                    .columns()
+                    .iter()
+                    .map(|column| match column.data_type() {
+                        DataType::Dictionary(_, values) => arrow_cast::cast(column, values),
+                        _ => Ok(column.clone()),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(io::Error::other)?;
+                let batch =
+                    RecordBatch::try_new(schema.clone(), columns).map_err(io::Error::other)?;
+                file.write(&batch).map_err(io::Error::other)
+            }
+            BatchWriter::IpcStream(stream) => stream.write(batch).map_err(io::Error::other),
+            #[cfg(feature = "parquet")]
+            BatchWriter::Parquet(parquet) => parquet.write(batch).map_err(io::Error::other),
+        }
+    }
+
+    /// Writes the footer, if the format has one, and flushes.
+    fn finish(self) -> io::Result<()> {
+        let writer = match self {
+            BatchWriter::IpcFile(file, _) => file.into_inner().map_err(io::Error::other),
+            BatchWriter::IpcStream(stream) => stream.into_inner().map_err(io::Error::other),
+            #[cfg(feature = "parquet")]
+            BatchWriter::Parquet(parquet) => parquet.into_inner().map_err(io::Error::other),
+        };
+        writer?.flush()
+    }
+}
+
+/// `schema` with dictionary-encoded columns as their plain values: an Arrow
+/// IPC file holds one dictionary per column, where every batch has its own.
+fn without_dictionaries(schema: &Schema) -> Schema {
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .map(|field| match field.data_type() {
+            DataType::Dictionary(_, values) => {
+                field.as_ref().clone().with_data_type(*values.clone())
+            }
+            _ => field.as_ref().clone(),
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+/// Writes the binary rows received on `rx` to `writer` in `format`, one of
+/// the columnar formats, a record batch of `BATCH_ROWS` rows at a time.
+pub(crate) fn write_batches<W: Write + Send>(
+    rx: mpsc::Receiver<Vec<u8>>,
+    writer: W,
+    format: OutputFormat,
+    decimals: &PriceDecimals,
+) -> io::Result<()> {
+    let mut columns = QuoteColumns::new();
+    let mut out = BatchWriter::new(format, writer, columns.schema.clone())?;
+    for chunk in rx {
+        for row in chunk.chunks_exact(ROW_LEN) {
+            columns.push_row(row, decimals);
+            if columns.len() == BATCH_ROWS {
+                out.write(&columns.finish())?;
+            }
+        }
+    }
+    if columns.len() > 0 {
+        out.write(&columns.finish())?;
+    }
+    out.finish()
+}
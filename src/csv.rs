@@ -0,0 +1,113 @@
+//! CSV output: one row per quote, one column per field.
+
+use crate::{
+    push_field, write_accept_time, write_packet_time, LineFormat, OutputMode, Quote, LEVELS,
+};
+
+/// Names of the columns `write_record` writes with `format`.
+///
+/// The market type is always a column, whatever `market_types` keeps, so
+/// the layout does not change with the filter. Levels are numbered from 1,
+/// the best price. `Extended` adds the issue
+/// seq no, market status and each side's total volume and valid quote
+/// count after the issue code, and each level's quote count after its
+/// quantity.
+fn columns(format: LineFormat) -> Vec<String> {
+    let mut columns: Vec<String> = ["packet_time", "accept_time", "market_type", "issue_code"]
+        .map(str::to_owned)
+        .into();
+    let extended = format.mode == OutputMode::Extended;
+    if extended {
+        for column in [
+            "issue_seq_no",
+            "market_status",
+            "total_bid_volume",
+            "bid_quotes",
+            "total_ask_volume",
+            "ask_quotes",
+        ] {
+            columns.push(column.to_owned());
+        }
+    }
+    for side in ["bid", "ask"] {
+        for level in 1..=LEVELS {
+            columns.push(format!("{side}_price_{level}"));
+            columns.push(format!("{side}_quantity_{level}"));
+            if extended {
+                columns.push(format!("{side}_quotes_{level}"));
+            }
+        }
+    }
+    columns
+}
+
+/// Appends the header row, newline included.
+pub(crate) fn write_header(out: &mut Vec<u8>, format: LineFormat, delimiter: u8) {
+    for (i, column) in columns(format).iter().enumerate() {
+        if i > 0 {
+            out.push(delimiter);
+        }
+        out.extend_from_slice(column.as_bytes());
+    }
+    out.push(b'\n');
+}
+
+/// Appends one quote's row, without a newline. Fields are never quoted:
+/// the delimiter must not be a character any field can hold.
+#[inline]
+pub(crate) fn write_record(
+    out: &mut Vec<u8>,
+    format: LineFormat,
+    delimiter: u8,
+    ts_sec: u32,
+    ts_nsec: u32,
+    quote: Quote,
+) {
+    write_packet_time(out, format.precision, ts_sec, ts_nsec);
+    out.push(delimiter);
+    write_accept_time(out, quote);
+    out.push(delimiter);
+    out.push(quote.market_type());
+    out.push(delimiter);
+    out.extend_from_slice(quote.issue_code());
+
+    let (price, int) = match format.decimals {
+        Some(decimals) => (Some(decimals.for_issue(quote.issue_code())), Some(0)),
+        None => (None, None),
+    };
+    let extended = format.mode == OutputMode::Extended;
+    if extended {
+        for (field, decimals) in [
+            (quote.issue_seq_no(), int),
+            (quote.market_status(), None),
+            (quote.total_bid_volume(), int),
+            (quote.bid_valid_quotes_total(), int),
+            (quote.total_ask_volume(), int),
+            (quote.ask_valid_quotes_total(), int),
+        ] {
+            out.push(delimiter);
+            push_field(out, field, decimals);
+        }
+    }
+
+    for (i, level) in quote.bids().enumerate() {
+        out.push(delimiter);
+        push_field(out, level.price, price);
+        out.push(delimiter);
+        push_field(out, level.quantity, int);
+        if extended {
+            out.push(delimiter);
+            push_field(out, quote.bid_valid_quotes(i), int);
+        }
+    }
+    for (i, level) in quote.asks().enumerate() {
+        out.push(delimiter);
+        push_field(out, level.price, price);
+        out.push(delimiter);
+        push_field(out, level.quantity, int);
+        if extended {
+            out.push(delimiter);
+            push_field(out, quote.ask_valid_quotes(i), int);
+        }
+    }
+}
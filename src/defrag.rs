@@ -0,0 +1,233 @@
+//! IPv4 fragment reassembly for UDP datagrams split across several frames.
+
+use crate::net::{Fragment, FragmentKey};
+use std::collections::HashMap;
+
+/// Largest IPv4 payload: a 65535-byte packet minus the minimal header.
+const MAX_PAYLOAD: usize = 65_535 - 20;
+
+/// Limits on datagrams held back while their fragments arrive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DefragOptions {
+    /// Packet-time seconds after its first fragment that an incomplete
+    /// datagram is dropped.
+    pub timeout_secs: u32,
+    /// Incomplete datagrams held per worker; the oldest is dropped first.
+    pub max_datagrams: usize,
+    /// Fragment payload bytes held per worker; the oldest is dropped first.
+    pub max_bytes: usize,
+}
+
+impl Default for DefragOptions {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            max_datagrams: 1024,
+            max_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// A datagram with at least one fragment received.
+struct Pending {
+    /// Packet time of the first fragment seen, in nanoseconds.
+    first_ns: u64,
+    /// Capture offset of the first fragment seen.
+    first_offset: usize,
+    payload: Vec<u8>,
+    /// Received byte ranges, sorted and merged.
+    ranges: Vec<(usize, usize)>,
+    /// Payload length, known once the last fragment arrives.
+    total: Option<usize>,
+}
+
+impl Pending {
+    /// Copies a fragment in; `None` if it contradicts what came before.
+    fn add(&mut self, frag: &Fragment) -> Option<()> {
+        let start = frag.offset;
+        let end = start + frag.data.len();
+        if end > MAX_PAYLOAD || (frag.more && !frag.data.len().is_multiple_of(8)) {
+            return None;
+        }
+        if !frag.more {
+            if self.total.is_some_and(|t| t != end) {
+                return None;
+            }
+            self.total = Some(end);
+        }
+        if self
+            .total
+            .is_some_and(|t| end > t || self.ranges.last().is_some_and(|r| r.1 > t))
+        {
+            return None;
+        }
+
+        if self.payload.len() < end {
+            self.payload.resize(end, 0);
+        }
+        self.payload[start..end].copy_from_slice(frag.data);
+
+        let i = self.ranges.partition_point(|r| r.1 < start);
+        let mut merged = (start, end);
+        let mut j = i;
+        while j < self.ranges.len() && self.ranges[j].0 <= end {
+            merged = (
+                merged.0.min(self.ranges[j].0),
+                merged.1.max(self.ranges[j].1),
+            );
+            j += 1;
+        }
+        self.ranges.splice(i..j, [merged]);
+        Some(())
+    }
+
+    fn is_complete(&self) -> bool {
+        self.total.is_some_and(|t| self.ranges == [(0, t)])
+    }
+}
+
+/// Collects fragments per (source, destination, id, protocol) and hands
+/// back each datagram's payload once every byte of it has arrived.
+///
+/// Time is the capture's packet time, so a datagram whose fragments are
+/// lost expires after `timeout_secs` of capture, however fast it is read.
+pub(crate) struct Reassembler {
+    options: DefragOptions,
+    pending: HashMap<FragmentKey, Pending>,
+    bytes: usize,
+    /// Earliest `first_ns` in `pending`, or `u64::MAX` when it is empty.
+    oldest_ns: u64,
+    /// First-fragment offsets of datagrams given up on since the last
+    /// `take_dropped`.
+    dropped: Vec<usize>,
+}
+
+impl Reassembler {
+    pub(crate) fn new(options: DefragOptions) -> Self {
+        Self {
+            options,
+            pending: HashMap::new(),
+            bytes: 0,
+            oldest_ns: u64::MAX,
+            dropped: Vec::new(),
+        }
+    }
+
+    /// Offsets of the datagrams dropped incomplete since the last call.
+    pub(crate) fn take_dropped(&mut self) -> impl Iterator<Item = usize> + '_ {
+        self.dropped.drain(..)
+    }
+
+    /// Gives up on every datagram still incomplete.
+    pub(crate) fn drop_all(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        self.dropped
+            .extend(pending.values().map(|p| p.first_offset));
+        self.bytes = 0;
+        self.oldest_ns = u64::MAX;
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Drops every datagram whose first fragment is older than the timeout.
+    pub(crate) fn expire(&mut self, now_ns: u64) {
+        let timeout_ns = self.options.timeout_secs as u64 * 1_000_000_000;
+        if now_ns.saturating_sub(self.oldest_ns) <= timeout_ns {
+            return;
+        }
+        self.pending.retain(|_, p| {
+            let keep = now_ns.saturating_sub(p.first_ns) <= timeout_ns;
+            if !keep {
+                self.bytes -= p.payload.len();
+                self.dropped.push(p.first_offset);
+            }
+            keep
+        });
+        self.oldest_ns = self
+            .pending
+            .values()
+            .map(|p| p.first_ns)
+            .min()
+            .unwrap_or(u64::MAX);
+    }
+
+    /// Adds a fragment seen at `now_ns` at capture offset `offset` and
+    /// returns the reassembled IP payload if it was the last one missing.
+    ///
+    /// With `start_new` false, only datagrams already pending are fed; that
+    /// lets a worker read past its range to finish what it started without
+    /// picking up datagrams owned by the next range.
+    pub(crate) fn insert(
+        &mut self,
+        frag: &Fragment,
+        offset: usize,
+        now_ns: u64,
+        start_new: bool,
+    ) -> Option<Vec<u8>> {
+        self.expire(now_ns);
+        if !self.pending.contains_key(&frag.key) {
+            if !start_new {
+                return None;
+            }
+            let len = frag.offset + frag.data.len();
+            self.evict_oldest_while(|r| {
+                r.pending.len() >= r.options.max_datagrams || r.bytes + len > r.options.max_bytes
+            });
+            self.pending.insert(
+                frag.key,
+                Pending {
+                    first_ns: now_ns,
+                    first_offset: offset,
+                    payload: Vec::new(),
+                    ranges: Vec::new(),
+                    total: None,
+                },
+            );
+            self.oldest_ns = self.oldest_ns.min(now_ns);
+        }
+
+        let pending = self.pending.get_mut(&frag.key)?;
+        let before = pending.payload.len();
+        let added = pending.add(frag);
+        self.bytes += pending.payload.len() - before;
+        if added.is_some() && !pending.is_complete() {
+            self.evict_oldest_while(|r| r.bytes > r.options.max_bytes);
+            return None;
+        }
+        let done = self.remove(&frag.key)?;
+        if added.is_none() {
+            self.dropped.push(done.first_offset);
+        }
+        added.map(|()| done.payload)
+    }
+
+    /// Drops the datagram with the earliest first fragment while `over`.
+    fn evict_oldest_while(&mut self, over: impl Fn(&Self) -> bool) {
+        while !self.pending.is_empty() && over(self) {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, p)| p.first_ns)
+                .map(|(k, _)| *k);
+            if let Some(dropped) = oldest.and_then(|key| self.remove(&key)) {
+                self.dropped.push(dropped.first_offset);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &FragmentKey) -> Option<Pending> {
+        let pending = self.pending.remove(key)?;
+        self.bytes -= pending.payload.len();
+        if pending.first_ns == self.oldest_ns {
+            self.oldest_ns = self
+                .pending
+                .values()
+                .map(|p| p.first_ns)
+                .min()
+                .unwrap_or(u64::MAX);
+        }
+        Some(pending)
+    }
+}
@@ -0,0 +1,266 @@
+//! Errors and the skipped-record report.
+
+use crate::QuoteField;
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Why a capture could not be parsed, or why one of its records was
+/// skipped. Offsets are byte offsets into the capture file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// Opening or reading an input failed.
+    Io(io::Error),
+    /// Writing the output failed.
+    Write(io::Error),
+    /// An error in the named file.
+    File {
+        path: PathBuf,
+        source: Box<ParseError>,
+    },
+    /// Neither a pcap nor a pcapng magic number.
+    BadMagic { offset: u64, found: u32 },
+    /// The file or section header is cut short.
+    TruncatedHeader { offset: u64 },
+    /// A record (or pcapng block) runs past the end of the file.
+    TruncatedRecord { offset: u64 },
+    /// A record header with impossible lengths or timestamps.
+    MalformedRecord { offset: u64 },
+    /// A link type the decoder does not handle.
+    UnsupportedLinkType { offset: u64, linktype: u16 },
+    /// A pcapng packet on an interface with no description before it.
+    UnknownInterface { offset: u64, interface: u32 },
+    /// A pcapng section in the other byte order from the first.
+    SectionByteOrder { offset: u64 },
+    /// A `B6034` marker that is not the start of a quote message.
+    StrayMarker { offset: u64 },
+    /// A message with the `B6034` header but not the quote length.
+    BadMessageLength { offset: u64, len: usize },
+    /// A message its decoder could not print.
+    MalformedMessage { offset: u64 },
+    /// A fragmented datagram that was dropped before all its fragments
+    /// arrived; `offset` is its first fragment's.
+    IncompleteDatagram { offset: u64 },
+    /// A quote that failed validation; `offset` is the quote's.
+    InvalidField { offset: u64, field: QuoteField },
+}
+
+impl ParseError {
+    /// Attributes the error to `path`.
+    pub(crate) fn in_file(self, path: impl Into<PathBuf>) -> Self {
+        ParseError::File {
+            path: path.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Short name of the error's kind, used to group the skip report.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::Io(_) => "I/O error",
+            ParseError::Write(_) => "write error",
+            ParseError::File { source, .. } => source.kind(),
+            ParseError::BadMagic { .. } => "bad magic number",
+            ParseError::TruncatedHeader { .. } => "truncated header",
+            ParseError::TruncatedRecord { .. } => "truncated record",
+            ParseError::MalformedRecord { .. } => "malformed record header",
+            ParseError::UnsupportedLinkType { .. } => "unsupported link type",
+            ParseError::UnknownInterface { .. } => "unknown interface",
+            ParseError::SectionByteOrder { .. } => "section byte order change",
+            ParseError::StrayMarker { .. } => "stray B6034 marker",
+            ParseError::BadMessageLength { .. } => "bad quote length",
+            ParseError::MalformedMessage { .. } => "malformed message",
+            ParseError::IncompleteDatagram { .. } => "incomplete fragmented datagram",
+            ParseError::InvalidField { field, .. } => field.kind(),
+        }
+    }
+
+    /// The underlying I/O error kind, if any.
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            ParseError::Io(e) | ParseError::Write(e) => Some(e.kind()),
+            ParseError::File { source, .. } => source.io_kind(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(e) | ParseError::Write(e) => write!(f, "{e}"),
+            ParseError::File { path, source } => write!(f, "{}: {source}", path.display()),
+            ParseError::BadMagic { offset, found } => write!(
+                f,
+                "not a pcap or pcapng file (magic {found:#010x} at offset {offset})"
+            ),
+            ParseError::UnsupportedLinkType { offset, linktype } => write!(
+                f,
+                "unsupported link type {linktype} at offset {offset} (expected Ethernet, \
+                 Linux cooked SLL/SLL2, raw IP or loopback)"
+            ),
+            ParseError::UnknownInterface { offset, interface } => write!(
+                f,
+                "packet at offset {offset} on undeclared interface {interface}"
+            ),
+            ParseError::SectionByteOrder { offset } => write!(
+                f,
+                "pcapng section at offset {offset} is in the other byte order from the first"
+            ),
+            ParseError::BadMessageLength { offset, len } => write!(
+                f,
+                "B6034 message at offset {offset} is {len} bytes, expected 215"
+            ),
+            ParseError::InvalidField { offset, field } => {
+                write!(f, "invalid {field} in the quote at offset {offset}")
+            }
+            ParseError::TruncatedHeader { offset }
+            | ParseError::TruncatedRecord { offset }
+            | ParseError::MalformedRecord { offset }
+            | ParseError::StrayMarker { offset }
+            | ParseError::MalformedMessage { offset }
+            | ParseError::IncompleteDatagram { offset } => {
+                write!(f, "{} at offset {offset}", self.kind())
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) | ParseError::Write(e) => Some(e),
+            ParseError::File { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::Io(e) | ParseError::Write(e) => e,
+            e => {
+                let kind = e.io_kind().unwrap_or(io::ErrorKind::InvalidData);
+                io::Error::new(kind, e)
+            }
+        }
+    }
+}
+
+/// Counts of the records skipped while parsing, by kind, with the first
+/// few kept as examples. Only collected when `ParseOptions::diagnostics`
+/// is set, except for the count of quotes rejected by validation.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    enabled: bool,
+    rejected: u64,
+    /// `(kind, count)` in the order each kind was first seen.
+    counts: Vec<(&'static str, u64)>,
+    samples: Vec<ParseError>,
+}
+
+impl Diagnostics {
+    /// Skipped records kept as examples.
+    pub const SAMPLES: usize = 16;
+
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Self::default()
+        }
+    }
+
+    /// Records a skipped record; `error` is only built when enabled.
+    #[inline]
+    pub(crate) fn skip(&mut self, error: impl FnOnce() -> ParseError) {
+        if self.enabled {
+            self.add(error(), 1);
+        }
+    }
+
+    /// Records a quote rejected by lenient validation.
+    pub(crate) fn reject(&mut self, error: ParseError) {
+        self.rejected += 1;
+        if self.enabled {
+            self.add(error, 1);
+        }
+    }
+
+    fn add(&mut self, error: ParseError, n: u64) {
+        let kind = error.kind();
+        match self.counts.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, count)) => *count += n,
+            None => self.counts.push((kind, n)),
+        }
+        if self.samples.len() < Self::SAMPLES {
+            self.samples.push(error);
+        }
+    }
+
+    /// Appends the report of a later part of the input.
+    pub(crate) fn merge(&mut self, later: Diagnostics) {
+        self.enabled |= later.enabled;
+        self.rejected += later.rejected;
+        for (kind, n) in later.counts {
+            match self.counts.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, count)) => *count += n,
+                None => self.counts.push((kind, n)),
+            }
+        }
+        let room = Self::SAMPLES - self.samples.len();
+        self.samples.extend(later.samples.into_iter().take(room));
+    }
+
+    /// Attributes every sample to `path`.
+    pub(crate) fn in_file(mut self, path: &Path) -> Self {
+        self.samples = self.samples.into_iter().map(|e| e.in_file(path)).collect();
+        self
+    }
+
+    /// Quotes skipped by `Validation::Lenient`, counted even when the rest
+    /// of the report is not collected.
+    pub fn rejected_quotes(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Total records skipped.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|(_, n)| n).sum()
+    }
+
+    /// `(kind, count)` for each kind of skipped record seen.
+    pub fn counts(&self) -> &[(&'static str, u64)] {
+        &self.counts
+    }
+
+    /// The first `SAMPLES` skipped records, in file order.
+    pub fn samples(&self) -> &[ParseError] {
+        &self.samples
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} records skipped", self.total())?;
+        for (kind, n) in &self.counts {
+            writeln!(f, "  {n:>10}  {kind}")?;
+        }
+        if !self.samples.is_empty() {
+            writeln!(f, "first {}:", self.samples.len())?;
+            for e in &self.samples {
+                writeln!(f, "  {e}")?;
+            }
+        }
+        Ok(())
+    }
+}
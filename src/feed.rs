@@ -0,0 +1,70 @@
+//! Framing of the exchange's messages inside a UDP payload.
+//!
+//! A datagram carries one or more messages back to back. Each starts with a
+//! 5-byte data type + info type header such as `B6034` and ends with an
+//! `END_OF_MESSAGE` byte.
+
+use memchr::memchr;
+use std::iter;
+
+/// KOSPI 200 futures quotes; other market types change the last byte.
+pub(crate) const QUOTE_HEADER: &[u8; HEADER_LEN] = b"B6034";
+/// Data type and info type of a quote, of any market type.
+pub(crate) const QUOTE_PREFIX: &[u8; HEADER_LEN - 1] = b"B603";
+pub(crate) const QUOTE_LEN: usize = 215;
+
+pub(crate) const HEADER_LEN: usize = 5;
+pub(crate) const END_OF_MESSAGE: u8 = 0xFF;
+
+/// Length fixed by the spec of the message at the start of `rest`, if any.
+/// Only quotes have one; anything else runs to the next `END_OF_MESSAGE`.
+#[inline]
+fn known_len(rest: &[u8]) -> Option<usize> {
+    (rest.len() >= HEADER_LEN && rest.starts_with(QUOTE_PREFIX)).then_some(QUOTE_LEN)
+}
+
+/// Whether `message` is a quote of any market type.
+#[inline]
+pub(crate) fn is_quote(message: &[u8]) -> bool {
+    message.len() == QUOTE_LEN && message.starts_with(QUOTE_PREFIX)
+}
+
+/// Length of the message at the start of `rest`, given the length its type
+/// fixes, if any. A message with no end marker takes the rest of the
+/// payload.
+#[inline]
+fn message_len(rest: &[u8], known: Option<usize>) -> usize {
+    match known {
+        Some(len) if len <= rest.len() => len,
+        _ => memchr(END_OF_MESSAGE, rest).map_or(rest.len(), |i| i + 1),
+    }
+}
+
+/// `(offset, message)` for each message in a UDP payload, in order.
+#[inline]
+pub(crate) fn messages(payload: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    messages_with(payload, known_len)
+}
+
+/// Like [`messages`], with `known_len(rest)` giving the fixed length of the
+/// message at the start of `rest`, if its type has one.
+#[inline]
+pub(crate) fn messages_with(
+    payload: &[u8],
+    known_len: impl Fn(&[u8]) -> Option<usize>,
+) -> impl Iterator<Item = (usize, &[u8])> {
+    let mut pos = 0;
+    iter::from_fn(move || {
+        let rest = payload.get(pos..).filter(|r| !r.is_empty())?;
+        let len = message_len(rest, known_len(rest));
+        let message = (pos, &rest[..len]);
+        pos += len;
+        Some(message)
+    })
+}
+
+/// `(offset, quote)` for each B603x quote in a UDP payload, in order.
+#[inline]
+pub(crate) fn quotes(payload: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    messages(payload).filter(|(_, m)| is_quote(m))
+}
@@ -0,0 +1,110 @@
+//! Issue code selection.
+
+use std::{collections::HashSet, fs, io, path::Path};
+
+/// Issue codes whose quotes are kept; an empty filter keeps every issue.
+///
+/// A pattern with no wildcard must match the whole code. `*` matches any
+/// run of characters and `?` any one, so `KR4101*` keeps every issue
+/// starting with `KR4101` and `KR4?01F3000?` a few contract months of two
+/// products. Codes are compared without their space padding.
+///
+/// ```
+/// use kopsi_200_pcap_parser::IssueFilter;
+///
+/// let mut filter = IssueFilter::new();
+/// filter.add("KR4101F30009").add("KR42*");
+/// assert!(filter.matches(b"KR4101F30009"));
+/// assert!(filter.matches(b"KR4201F32606"));
+/// assert!(!filter.matches(b"KR4101F60006"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IssueFilter {
+    exact: HashSet<Vec<u8>>,
+    prefixes: Vec<Vec<u8>>,
+    globs: Vec<Vec<u8>>,
+}
+
+impl IssueFilter {
+    /// A filter that keeps every issue until a pattern is added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also keeps the issues matching `pattern`.
+    pub fn add(&mut self, pattern: &str) -> &mut Self {
+        let pattern = pattern.trim().as_bytes();
+        let wildcard = |b: &u8| matches!(b, b'*' | b'?');
+        match pattern.iter().position(wildcard) {
+            None => {
+                self.exact.insert(pattern.to_vec());
+            }
+            // A trailing `*` is the common case and needs no backtracking.
+            Some(i) if i == pattern.len() - 1 && pattern[i] == b'*' => {
+                self.prefixes.push(pattern[..i].to_vec());
+            }
+            Some(_) => self.globs.push(pattern.to_vec()),
+        }
+        self
+    }
+
+    /// Adds one pattern per line of `text`. Blank lines and lines starting
+    /// with `#` are skipped.
+    pub fn add_lines(&mut self, text: &str) -> &mut Self {
+        for line in text.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                self.add(line);
+            }
+        }
+        self
+    }
+
+    /// Adds the patterns listed in a file, as by [`add_lines`](Self::add_lines).
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> io::Result<&mut Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(self.add_lines(&text))
+    }
+
+    /// Whether every issue is kept.
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.prefixes.is_empty() && self.globs.is_empty()
+    }
+
+    /// Whether quotes for `issue_code` are kept.
+    #[inline]
+    pub fn matches(&self, issue_code: &[u8]) -> bool {
+        self.is_empty()
+            || self.exact.contains(issue_code)
+            || self.prefixes.iter().any(|p| issue_code.starts_with(p))
+            || self.globs.iter().any(|g| glob_matches(g, issue_code))
+    }
+}
+
+/// Matches `text` against a pattern of literal bytes, `*` and `?`.
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and the text position it is tried up to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more byte and retry.
+                Some((sp, st)) => {
+                    star = Some((sp, st + 1));
+                    p = sp + 1;
+                    t = st + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
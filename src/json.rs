@@ -0,0 +1,156 @@
+//! JSON Lines output: one object per quote.
+
+use crate::{
+    parse_digits, push_2d, push_int, push_nd, write_packet_time, LineFormat, OutputMode, Quote,
+    KST_OFFSET_SECS,
+};
+
+/// Appends one quote's object, without a newline:
+///
+/// ```text
+/// {"packet_time":"2011-02-16T09:00:00.011+09:00","accept_time":"2011-02-16T08:59:58.950+09:00",
+///  "market_type":"4","issue_code":"KR4101F30009","bids":[{"price":22433,"quantity":4132},...],"asks":[...]}
+/// ```
+///
+/// Levels are listed from the best price. Quantities and counts are
+/// integers, and prices too unless `format` decodes them, when they carry
+/// their implied decimals. A numeric field that is not all digits is
+/// `null`. The accept time takes the packet's date. The market type is
+/// always present, whatever `market_types` keeps. `Extended` adds
+/// `issue_seq_no`, `market_status`, each side's `total_*_volume` and
+/// `*_quotes`, and each level's `quotes`.
+#[inline]
+pub(crate) fn write_record(
+    out: &mut Vec<u8>,
+    format: LineFormat,
+    ts_sec: u32,
+    ts_nsec: u32,
+    quote: Quote,
+) {
+    out.extend_from_slice(b"{\"packet_time\":\"");
+    write_date(out, ts_sec);
+    write_packet_time(out, format.precision, ts_sec, ts_nsec);
+    out.extend_from_slice(b"+09:00\",\"accept_time\":\"");
+    write_date(out, ts_sec);
+    let accept = quote.accept_time();
+    for (i, pair) in accept.chunks(2).enumerate() {
+        if i > 0 {
+            out.push(if i == 3 { b'.' } else { b':' });
+        }
+        push_str_bytes(out, pair);
+    }
+    out.extend_from_slice(b"0+09:00\"");
+    out.extend_from_slice(b",\"market_type\":\"");
+    push_str_bytes(out, &[quote.market_type()]);
+    out.push(b'"');
+    out.extend_from_slice(b",\"issue_code\":\"");
+    push_str_bytes(out, quote.issue_code());
+    out.push(b'"');
+
+    let price = format
+        .decimals
+        .map_or(0, |decimals| decimals.for_issue(quote.issue_code()));
+    let extended = format.mode == OutputMode::Extended;
+    if extended {
+        out.extend_from_slice(b",\"issue_seq_no\":");
+        push_number(out, quote.issue_seq_no(), 0);
+        out.extend_from_slice(b",\"market_status\":\"");
+        push_str_bytes(out, quote.market_status());
+        out.extend_from_slice(b"\",\"total_bid_volume\":");
+        push_number(out, quote.total_bid_volume(), 0);
+        out.extend_from_slice(b",\"bid_quotes\":");
+        push_number(out, quote.bid_valid_quotes_total(), 0);
+        out.extend_from_slice(b",\"total_ask_volume\":");
+        push_number(out, quote.total_ask_volume(), 0);
+        out.extend_from_slice(b",\"ask_quotes\":");
+        push_number(out, quote.ask_valid_quotes_total(), 0);
+    }
+
+    out.extend_from_slice(b",\"bids\":[");
+    for (i, level) in quote.bids().enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        out.extend_from_slice(b"{\"price\":");
+        push_number(out, level.price, price);
+        out.extend_from_slice(b",\"quantity\":");
+        push_number(out, level.quantity, 0);
+        if extended {
+            out.extend_from_slice(b",\"quotes\":");
+            push_number(out, quote.bid_valid_quotes(i), 0);
+        }
+        out.push(b'}');
+    }
+    out.extend_from_slice(b"],\"asks\":[");
+    for (i, level) in quote.asks().enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        out.extend_from_slice(b"{\"price\":");
+        push_number(out, level.price, price);
+        out.extend_from_slice(b",\"quantity\":");
+        push_number(out, level.quantity, 0);
+        if extended {
+            out.extend_from_slice(b",\"quotes\":");
+            push_number(out, quote.ask_valid_quotes(i), 0);
+        }
+        out.push(b'}');
+    }
+    out.extend_from_slice(b"]}");
+}
+
+/// Formats the KST date of `ts_sec` and the `T` after it, `YYYY-MM-DDT`.
+#[inline]
+fn write_date(out: &mut Vec<u8>, ts_sec: u32) {
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant's
+    // `civil_from_days`.
+    let days = (ts_sec as u64 + KST_OFFSET_SECS) / 86_400 + 719_468;
+    let era = days / 146_097;
+    let doe = days % 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    push_nd(out, year, 4);
+    out.push(b'-');
+    push_2d(out, month);
+    out.push(b'-');
+    push_2d(out, day);
+    out.push(b'T');
+}
+
+/// Writes a fixed-width digit field as a JSON number with `decimals` of
+/// its digits after the point, or `null` if it is not all digits.
+#[inline]
+fn push_number(out: &mut Vec<u8>, field: &[u8], decimals: u8) {
+    let Some(n) = parse_digits(field) else {
+        out.extend_from_slice(b"null");
+        return;
+    };
+    if decimals == 0 {
+        return push_int(out, n);
+    }
+    let scale = 10u64.pow(decimals as u32);
+    push_int(out, n / scale);
+    out.push(b'.');
+    push_nd(out, n % scale, decimals as usize);
+}
+
+/// Writes feed bytes inside a JSON string, escaping anything that is not
+/// printable ASCII.
+#[inline]
+fn push_str_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => out.extend_from_slice(&[b'\\', b]),
+            b' '..=b'~' => out.push(b),
+            _ => {
+                out.extend_from_slice(b"\\u00");
+                const HEX: &[u8; 16] = b"0123456789abcdef";
+                out.extend_from_slice(&[HEX[(b >> 4) as usize], HEX[(b & 0xf) as usize]]);
+            }
+        }
+    }
+}
@@ -1,23 +1,38 @@
+mod capture;
+#[cfg(feature = "arrow")]
+mod columnar;
+mod csv;
+mod defrag;
+mod error;
+mod feed;
+mod filter;
+mod json;
+mod message;
+mod net;
+mod pcapng;
+mod price;
+mod quote;
+mod reader;
+mod sink;
+
+use capture::Capture;
+use defrag::Reassembler;
+use feed::{QUOTE_LEN, QUOTE_PREFIX};
 use memchr::memmem;
-use memmap2::{Advice, MmapOptions};
+use memmap2::{Advice, Mmap, MmapOptions};
 use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
     fs::File,
     io::{self, Write},
     mem,
+    num::NonZeroUsize,
+    ops::ControlFlow,
     path::Path,
-    sync::mpsc,
+    sync::{mpsc, Arc},
     thread,
 };
 
-// Packet layout constants:
-// 16 bytes pcap per-packet record header
-// 14 bytes Ethernet header
-// 20 bytes IPv4 header
-//  8 bytes UDP header
-const HDR_TO_PAYLOAD: usize = 16 + 14 + 20 + 8;
-const PAYLOAD_LEN: usize = 215;
-const RECORD_DATA_LEN: u32 = (14 + 20 + 8 + PAYLOAD_LEN) as u32;
-
 /// Each boundary between worker slices is extended by this many bytes so a
 /// pattern straddling the split is still visible to the owning thread.
 const OVERLAP: usize = 4; // len("B6034") - 1
@@ -25,45 +40,323 @@ const OVERLAP: usize = 4; // len("B6034") - 1
 /// Flush a chunk to the printer thread once it reaches this size.
 const CHUNK_BYTES: usize = 16 * 1024;
 
+/// Bytes of mmap handed to a worker at a time by the windowed pipeline.
+const WINDOW_BYTES: usize = 1024 * 1024;
+
+/// Finished windows a worker may queue before it blocks on the consumer.
+const WINDOWS_IN_FLIGHT: usize = 2;
+
+/// Furthest a structural scan reads past its range to finish reassembling
+/// datagrams whose first fragment it owns.
+const DEFRAG_LOOKAHEAD_BYTES: usize = 1024 * 1024;
+
+/// Packet times are UTC; the exchange (and our output) uses KST.
+const KST_OFFSET_SECS: u64 = 9 * 3600;
+
+/// The feed guarantees packet time - quote accept time never exceeds 3s.
+pub const DEFAULT_MAX_SKEW_CS: u32 = 300;
+
+#[cfg(feature = "arrow")]
+pub use columnar::{quote_schema, QuoteBatches};
+pub use defrag::DefragOptions;
+pub use error::{Diagnostics, ParseError};
+pub use filter::IssueFilter;
+pub use message::{MessageDecoder, MessageRegistry, MessageType, QuoteDecoder, RawDecoder};
+pub use price::{parse_digits, Price, PriceDecimals, MAX_DECIMALS};
+pub use quote::{payload_quotes, Level, MarketTypes, Quote, QuoteField, LEVELS};
+pub use reader::{QuoteReader, QuoteRecord, SortedQuoteReader};
+pub use sink::{process_pcap_file, process_pcap_files, QuoteSink};
+
 pub const PCAP_FILE_PATH: &str = "fixtures/mdf-kospi200.20110216-0.pcap 2";
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub enum PacketOrdering {
+    #[default]
     Default,
+    /// Exact accept-time order: index the whole capture, then sort once.
     QuoteAcceptTime,
+    /// Accept-time order in bounded memory, relying on the feed's skew bound
+    /// (see [`ParseOptions::max_skew_cs`]).
+    StreamingQuoteAcceptTime,
+}
+
+/// How many fractional digits of packet time to print.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimePrecision {
+    /// `HH:MM:SS.mmm`
+    #[default]
+    Millis,
+    /// `HH:MM:SS.uuuuuu`
+    Micros,
+    /// `HH:MM:SS.nnnnnnnnn`; only meaningful for nanosecond captures.
+    Nanos,
+}
+
+/// Which quote fields each output line carries.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Times, issue code and the ten price levels.
+    #[default]
+    Standard,
+    /// Every field of the message: also the issue seq no, market status,
+    /// total volumes and the valid quote counts, total and per level.
+    Extended,
+}
+
+/// How each quote is written out. The columnar formats come with the
+/// `arrow` and `parquet` features, so matches need a wildcard arm.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// Space-separated lines with each level as `quantity@price`.
+    #[default]
+    Text,
+    /// One row per quote and one column per field, separated by
+    /// `delimiter`, after a header row naming the columns if `header` is
+    /// set. Fields are never quoted, so the delimiter must not be a digit,
+    /// letter, `.`, `:` or `"`.
+    Csv { delimiter: u8, header: bool },
+    /// One JSON object per line, with numeric prices and quantities and
+    /// ISO 8601 times.
+    JsonLines,
+    /// One Arrow IPC file (Feather v2) for the whole run, in the columns of
+    /// [`quote_schema`], except that `market_type` and `issue_code` are
+    /// plain strings: an IPC file cannot change dictionaries between
+    /// batches. `output` and `numbers` do not apply.
+    #[cfg(feature = "arrow")]
+    ArrowFile,
+    /// One Arrow IPC stream for the whole run, in the columns of
+    /// [`quote_schema`]. `output` and `numbers` do not apply.
+    #[cfg(feature = "arrow")]
+    ArrowStream,
+    /// One Parquet file for the whole run, in the columns of
+    /// [`quote_schema`]: times as nanoseconds since the epoch, issue codes
+    /// dictionary-encoded and prices and quantities as integers. `output`
+    /// and `numbers` do not apply.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+#[cfg(feature = "arrow")]
+impl OutputFormat {
+    /// Whether quotes are written as record batches rather than lines.
+    fn is_columnar(self) -> bool {
+        !matches!(
+            self,
+            OutputFormat::Text | OutputFormat::Csv { .. } | OutputFormat::JsonLines
+        )
+    }
+}
+
+/// How prices, quantities and counts are printed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// The zero-padded digits as sent, e.g. `0000150@25000`.
+    #[default]
+    Raw,
+    /// Integers without padding and prices with their implied decimals
+    /// (see [`ParseOptions::price_decimals`]), e.g. `150@250.00`.
+    Decoded,
+}
+
+/// How quotes are located in the capture.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ScanMode {
+    /// `memmem` search for `B6034`, checked against the enclosing record's
+    /// length. Fastest, especially for plain Ethernet + IPv4 + UDP framing.
+    #[default]
+    Pattern,
+    /// Walk every record header, decode the link, IP and UDP headers and
+    /// keep payloads sent to the feed ports that start with `B6034`.
+    Structural,
+}
+
+/// What to do with quotes that fail [`Quote::validate`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Validation {
+    /// Pass every quote through unchecked.
+    #[default]
+    Off,
+    /// Skip invalid quotes; they are counted in
+    /// [`Diagnostics::rejected_quotes`], and with `diagnostics` set the
+    /// report names the failing field of each.
+    Lenient,
+    /// Stop at the first invalid quote with `ParseError::InvalidField`.
+    Strict,
+}
+
+/// The clock a [`TimeRange`] bounds.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeField {
+    /// When the packet was captured.
+    #[default]
+    Packet,
+    /// The quote's accept time.
+    Accept,
+}
+
+/// Quotes whose time of day (KST, in centiseconds) is in `[from_cs,
+/// to_cs)`; every quote by default.
+///
+/// A packet time range also skips the records outside it without reading
+/// them, by bisecting the capture on record timestamps, so a narrow range
+/// of a large capture is fast. That assumes the records are in time
+/// order; an accept time range is checked quote by quote.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    pub field: TimeField,
+    pub from_cs: u32,
+    pub to_cs: u32,
+}
+
+impl Default for TimeRange {
+    fn default() -> Self {
+        Self {
+            field: TimeField::Packet,
+            from_cs: 0,
+            to_cs: u32::MAX,
+        }
+    }
+}
+
+impl TimeRange {
+    /// Whether the range keeps every quote.
+    pub fn is_all(&self) -> bool {
+        self.from_cs == 0 && self.to_cs == u32::MAX
+    }
+
+    /// Whether a quote received at `(ts_sec, ts_nsec)` is in the range.
+    #[inline]
+    pub fn contains(&self, ts_sec: u32, ts_nsec: u32, quote: &Quote) -> bool {
+        let cs = match self.field {
+            TimeField::Packet => packet_time_cs(ts_sec, ts_nsec),
+            TimeField::Accept => quote.accept_time_cs(),
+        };
+        (self.from_cs..self.to_cs).contains(&cs)
+    }
+
+    /// Whether a packet time range keeps a record received at `(ts_sec,
+    /// ts_nsec)`; an accept time range keeps every record.
+    #[inline]
+    fn contains_packet(&self, ts_sec: u32, ts_nsec: u32) -> bool {
+        self.field != TimeField::Packet
+            || (self.from_cs..self.to_cs).contains(&packet_time_cs(ts_sec, ts_nsec))
+    }
+}
+
+/// Knobs shared by every input file of a run.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    pub ordering: PacketOrdering,
+    /// Worker thread count; `None` uses every available core.
+    pub threads: Option<NonZeroUsize>,
+    /// Largest packet time - accept time, in centiseconds, that
+    /// `StreamingQuoteAcceptTime` waits for before emitting a quote.
+    pub max_skew_cs: u32,
+    /// Packet time precision. Timestamps are carried at the capture's own
+    /// resolution (µs or ns for pcap, `if_tsresol` for pcapng) and only
+    /// truncated when printed.
+    pub precision: TimePrecision,
+    pub output: OutputMode,
+    pub format: OutputFormat,
+    pub numbers: NumberFormat,
+    /// Implied decimals of each product's prices, for `NumberFormat::Decoded`.
+    pub price_decimals: PriceDecimals,
+    pub scan: ScanMode,
+    /// Market types whose quotes are kept; KOSPI 200 futures by default.
+    /// Unless exactly one is kept, each text line names the quote's market
+    /// type; CSV and JSON records always do.
+    pub market_types: MarketTypes,
+    /// Issue codes whose quotes are kept; every issue by default. Quotes
+    /// are dropped before they are validated or formatted.
+    pub issues: IssueFilter,
+    /// Time of day bounds on the quotes kept.
+    pub time_range: TimeRange,
+    /// IPv4 reassembly limits for `ScanMode::Structural`.
+    pub defrag: DefragOptions,
+    /// Count the records skipped as malformed (and keep a few examples)
+    /// in `ParseSummary::diagnostics`.
+    pub diagnostics: bool,
+    pub validation: Validation,
+    /// Print every message the registry decodes, `packet-time type
+    /// fields`, in packet order, instead of just the quotes. Messages are
+    /// found by a structural scan and a packet `time_range` applies;
+    /// `ordering`, `scan`, `market_types`, `issues`, an accept time range,
+    /// `validation` and the quote output options do not.
+    pub messages: Option<Arc<MessageRegistry>>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            ordering: PacketOrdering::Default,
+            threads: None,
+            max_skew_cs: DEFAULT_MAX_SKEW_CS,
+            precision: TimePrecision::Millis,
+            output: OutputMode::Standard,
+            format: OutputFormat::Text,
+            numbers: NumberFormat::Raw,
+            price_decimals: PriceDecimals::default(),
+            scan: ScanMode::Pattern,
+            market_types: MarketTypes::default(),
+            issues: IssueFilter::default(),
+            time_range: TimeRange::default(),
+            defrag: DefragOptions::default(),
+            diagnostics: false,
+            validation: Validation::Off,
+            messages: None,
+        }
+    }
+}
+
+/// Counters collected while parsing.
+#[derive(Debug, Default)]
+pub struct ParseSummary {
+    /// Quotes whose packet and accept times are more than `max_skew_cs`
+    /// apart. Only tracked by `StreamingQuoteAcceptTime`, where late quotes
+    /// may be printed after rows with a later accept time, and early ones
+    /// are printed as soon as they are read.
+    pub skew_violations: u64,
+    /// Skipped records, when `ParseOptions::diagnostics` is set.
+    pub diagnostics: Diagnostics,
 }
 
 #[derive(Clone, Copy)]
 struct QuoteAcceptIndex {
     accept_time_cs: u32,
-    global_pos: u32,
-    local_start: u32,
-    local_len: u32,
+    global_pos: u64,
+    local_start: usize,
+    local_len: usize,
 }
 
-#[derive(Clone, Copy)]
+// Field order matters: the derived Ord sorts by (accept_time_cs, global_pos).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct MergedQuoteAcceptIndex {
     accept_time_cs: u32,
-    global_pos: u32,
+    global_pos: u64,
     worker_id: usize,
-    local_start: u32,
-    local_len: u32,
+    local_start: usize,
+    local_len: usize,
 }
 
-#[inline]
-fn accept_time_cs(data: &[u8]) -> u32 {
-    let hh = aa(&data[206..208]);
-    let mm = aa(&data[208..210]);
-    let ss = aa(&data[210..212]);
-    let cc = aa(&data[212..214]);
-    hh * 360_000 + mm * 6_000 + ss * 100 + cc // centiseconds since midnight
+/// One window's worth of streaming reorder output.
+struct StreamWindow {
+    buf: Vec<u8>,
+    index: Vec<QuoteAcceptIndex>,
+    /// Rows whose accept time is past their packet time plus the skew
+    /// bound, in file order; they bypass the heap.
+    early: Vec<QuoteAcceptIndex>,
+    /// Latest packet time in the window, centiseconds-of-day KST.
+    watermark_cs: u32,
+    skew_violations: u64,
+    diagnostics: Diagnostics,
 }
 
-/// Manually parse exactly two ASCII digits, used for HH, MM, SS, etc.
-/// Assumes both bytes are valid digits in 0..9.
+/// Packet time as centiseconds since midnight KST, comparable with
+/// `Quote::accept_time_cs`. Captures are assumed not to span midnight.
 #[inline]
-fn aa(b: &[u8]) -> u32 {
-    (b[0] - b'0') as u32 * 10 + (b[1] - b'0') as u32
+fn packet_time_cs(ts_sec: u32, ts_nsec: u32) -> u32 {
+    (((ts_sec as u64 + KST_OFFSET_SECS) % 86_400) * 100 + ts_nsec as u64 / 10_000_000) as u32
 }
 
 // Write a 2-digit number into the output buffer.
@@ -81,93 +374,257 @@ fn push_3d(out: &mut Vec<u8>, n: u64) {
     out.push(b'0' + (n % 10) as u8);
 }
 
+// Write `n` zero-padded to exactly `width` digits into the output buffer.
+#[inline]
+fn push_nd(out: &mut Vec<u8>, mut n: u64, width: usize) {
+    let start = out.len();
+    out.resize(start + width, b'0');
+    for d in out[start..].iter_mut().rev() {
+        *d = b'0' + (n % 10) as u8;
+        n /= 10;
+    }
+}
+
+/// How `write_quote` lays out a line.
+#[derive(Clone, Copy)]
+struct LineFormat<'a> {
+    precision: TimePrecision,
+    mode: OutputMode,
+    /// Implied decimals when numbers are decoded; `None` prints them raw.
+    decimals: Option<&'a PriceDecimals>,
+    /// Print the market type before the issue code in text lines. CSV and
+    /// JSON always carry it.
+    market_type: bool,
+    style: OutputFormat,
+}
+
+impl<'a> LineFormat<'a> {
+    fn new(options: &'a ParseOptions) -> Self {
+        Self {
+            precision: options.precision,
+            mode: options.output,
+            decimals: (options.numbers == NumberFormat::Decoded).then_some(&options.price_decimals),
+            market_type: options.market_types.single().is_none(),
+            style: options.format,
+        }
+    }
+}
+
+// Write `n` in decimal, without padding, into the output buffer.
+#[inline]
+fn push_int(out: &mut Vec<u8>, n: u64) {
+    let mut digits = [0u8; 20];
+    let mut i = digits.len();
+    let mut n = n;
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(&digits[i..]);
+}
+
+// Write a fixed-width digit field: verbatim when `decimals` is `None`,
+// otherwise as a number with `decimals` of its digits after the point.
+// Fields that are not all digits are always copied verbatim.
+#[inline]
+fn push_field(out: &mut Vec<u8>, field: &[u8], decimals: Option<u8>) {
+    let Some(decimals) = decimals else {
+        out.extend_from_slice(field);
+        return;
+    };
+    let Some(n) = parse_digits(field) else {
+        out.extend_from_slice(field);
+        return;
+    };
+    if decimals == 0 {
+        return push_int(out, n);
+    }
+    let scale = 10u64.pow(decimals as u32);
+    push_int(out, n / scale);
+    out.push(b'.');
+    push_nd(out, n % scale, decimals as usize);
+}
+
 /// Formats one B6034 quote line into `out` and returns the accept-time
 /// in centiseconds-of-day (heap/sort key).
+///
+/// `Extended` adds the issue seq no and market status after the issue
+/// code, each side's total volume and valid quote count (`volume/count`)
+/// on the outside of its levels, and each level's quote count after its
+/// price (`quantity@price/count`).
 #[inline]
-fn write_quote(out: &mut Vec<u8>, ts_sec: u32, ts_usec: u32, data: &[u8]) -> u32 {
-    // Packet time.
-    let secs = ts_sec as u64 + 9 * 3600;
+fn write_quote(
+    out: &mut Vec<u8>,
+    format: LineFormat,
+    ts_sec: u32,
+    ts_nsec: u32,
+    quote: Quote,
+) -> u32 {
+    match format.style {
+        OutputFormat::Text => {
+            write_packet_time(out, format.precision, ts_sec, ts_nsec);
+            out.push(b' ');
+            write_quote_fields(out, format, quote);
+        }
+        OutputFormat::Csv { delimiter, .. } => {
+            csv::write_record(out, format, delimiter, ts_sec, ts_nsec, quote)
+        }
+        OutputFormat::JsonLines => json::write_record(out, format, ts_sec, ts_nsec, quote),
+        // The columnar formats: binary rows, with no line to end.
+        #[cfg(feature = "arrow")]
+        _ => {
+            columnar::write_row(out, ts_sec, ts_nsec, quote);
+            return quote.accept_time_cs();
+        }
+    }
+    out.push(b'\n');
+
+    quote.accept_time_cs()
+}
+
+/// Formats the packet time, `HH:MM:SS.fff` in KST.
+#[inline]
+fn write_packet_time(out: &mut Vec<u8>, precision: TimePrecision, ts_sec: u32, ts_nsec: u32) {
+    let secs = ts_sec as u64 + KST_OFFSET_SECS;
     push_2d(out, (secs / 3600) % 24);
     out.push(b':');
     push_2d(out, (secs / 60) % 60);
     out.push(b':');
     push_2d(out, secs % 60);
     out.push(b'.');
-    push_3d(out, ts_usec as u64 / 1000);
-    out.push(b' ');
+    match precision {
+        TimePrecision::Millis => push_3d(out, ts_nsec as u64 / 1_000_000),
+        TimePrecision::Micros => push_nd(out, ts_nsec as u64 / 1_000, 6),
+        TimePrecision::Nanos => push_nd(out, ts_nsec as u64, 9),
+    }
+}
 
-    // Quote accept time.
-    out.extend_from_slice(&data[206..208]);
+/// Formats the accept time, `HH:MM:SS.ff0`, to match the packet time's
+/// milliseconds.
+#[inline]
+fn write_accept_time(out: &mut Vec<u8>, quote: Quote) {
+    let accept = quote.accept_time();
+    out.extend_from_slice(&accept[0..2]);
     out.push(b':');
-    out.extend_from_slice(&data[208..210]);
+    out.extend_from_slice(&accept[2..4]);
     out.push(b':');
-    out.extend_from_slice(&data[210..212]);
+    out.extend_from_slice(&accept[4..6]);
     out.push(b'.');
-    out.extend_from_slice(&data[212..214]);
+    out.extend_from_slice(&accept[6..8]);
     out.push(b'0');
+}
+
+/// Formats a quote's fields from its accept time on, without a newline.
+#[inline]
+fn write_quote_fields(out: &mut Vec<u8>, format: LineFormat, quote: Quote) {
+    write_accept_time(out, quote);
     out.push(b' ');
 
-    // Issue code.
-    let code_end = data[5..17]
-        .iter()
-        .rposition(|&b| b != b' ')
-        .map_or(0, |i| i + 1);
-    out.extend_from_slice(&data[5..5 + code_end]);
+    if format.market_type {
+        out.push(quote.market_type());
+        out.push(b' ');
+    }
+    out.extend_from_slice(quote.issue_code());
+
+    // Decimals for prices and for plain integers, when decoding.
+    let (price, int) = match format.decimals {
+        Some(decimals) => (Some(decimals.for_issue(quote.issue_code())), Some(0)),
+        None => (None, None),
+    };
+    let extended = format.mode == OutputMode::Extended;
+    if extended {
+        out.push(b' ');
+        push_field(out, quote.issue_seq_no(), int);
+        out.push(b' ');
+        out.extend_from_slice(quote.market_status());
+        out.push(b' ');
+        push_field(out, quote.total_bid_volume(), int);
+        out.push(b'/');
+        push_field(out, quote.bid_valid_quotes_total(), int);
+    }
 
-    // Bids.
-    for i in (0..5usize).rev() {
+    // Bids, worst to best.
+    for i in (0..LEVELS).rev() {
+        let level = quote.bid(i);
         out.push(b' ');
-        out.extend_from_slice(&data[34 + i * 12..41 + i * 12]);
+        push_field(out, level.quantity, int);
         out.push(b'@');
-        out.extend_from_slice(&data[29 + i * 12..34 + i * 12]);
+        push_field(out, level.price, price);
+        if extended {
+            out.push(b'/');
+            push_field(out, quote.bid_valid_quotes(i), int);
+        }
     }
 
-    // Asks.
-    for i in 0..5usize {
+    // Asks, best to worst.
+    for (i, level) in quote.asks().enumerate() {
         out.push(b' ');
-        out.extend_from_slice(&data[101 + i * 12..108 + i * 12]);
+        push_field(out, level.quantity, int);
         out.push(b'@');
-        out.extend_from_slice(&data[96 + i * 12..101 + i * 12]);
+        push_field(out, level.price, price);
+        if extended {
+            out.push(b'/');
+            push_field(out, quote.ask_valid_quotes(i), int);
+        }
     }
-    out.push(b'\n');
 
-    accept_time_cs(data)
+    if extended {
+        out.push(b' ');
+        push_field(out, quote.total_ask_volume(), int);
+        out.push(b'/');
+        push_field(out, quote.ask_valid_quotes_total(), int);
+    }
 }
 
 /// Send `chunk` over `tx`, replacing it with a fresh pre-allocated buffer.
 #[inline]
-fn flush_chunk(tx: &mpsc::SyncSender<Vec<u8>>, chunk: &mut Vec<u8>) -> io::Result<()> {
+fn flush_chunk(tx: &mpsc::SyncSender<Vec<u8>>, chunk: &mut Vec<u8>) -> Result<(), ParseError> {
     let full = mem::replace(chunk, Vec::with_capacity(CHUNK_BYTES + 256));
-    tx.send(full)
-        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "printer thread exited"))
+    send_chunk(tx, full)
+}
+
+/// Hand a finished buffer to the printer thread.
+#[inline]
+fn send_chunk(tx: &mpsc::SyncSender<Vec<u8>>, chunk: Vec<u8>) -> Result<(), ParseError> {
+    // The printer only hangs up after a write error, which it reports.
+    tx.send(chunk).map_err(|_| {
+        ParseError::Write(io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            "printer thread exited",
+        ))
+    })
 }
 
 pub fn read_pcap_file<W: Write + Send + 'static>(
     path: impl AsRef<Path>,
     ordering: PacketOrdering,
     writer: W,
-) -> io::Result<()> {
-    let file = File::open(path)?;
-    let mmap = unsafe { MmapOptions::new().populate().map(&file)? };
-    let _ = mmap.advise(Advice::Sequential);
-
-    if mmap.len() < 24 {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "pcap too small"));
-    }
-
-    let le = match &mmap[0..4] {
-        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => true,
-        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => false,
-        _ => {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "not a pcap file",
-            ))
-        }
+) -> Result<(), ParseError> {
+    let options = ParseOptions {
+        ordering,
+        ..ParseOptions::default()
     };
+    read_pcap_files(&[path], &options, writer).map(|_| ())
+}
 
-    let file_len = mmap.len();
-
+/// Parses each capture in `paths` in turn and writes all quotes to `writer`.
+///
+/// Files are not merged: with `QuoteAcceptTime` each file is sorted on its
+/// own, which matches how the feed is rotated into consecutive captures.
+/// Errors opening or parsing a file are prefixed with its path.
+pub fn read_pcap_files<P, W>(
+    paths: &[P],
+    options: &ParseOptions,
+    writer: W,
+) -> Result<ParseSummary, ParseError>
+where
+    P: AsRef<Path>,
+    W: Write + Send + 'static,
+{
     // ── Core pinning ─────────────────────────────────────────────────────────
     // Main thread → core 0.  Printer thread → core 1.
     // Workers inside thread::scope share cores[0..] with the idle main thread.
@@ -181,102 +638,144 @@ pub fn read_pcap_file<W: Write + Send + 'static>(
     // Bounded channel (cap 16): parser blocks if printer falls behind.
     let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(16);
 
+    #[cfg(feature = "arrow")]
+    let columnar = (options.format.is_columnar() && options.messages.is_none())
+        .then(|| (options.format, options.price_decimals.clone()));
+
     let printer = thread::spawn(move || -> io::Result<()> {
         if let Some(c) = printer_core {
             core_affinity::set_for_current(c);
         }
+        #[cfg(feature = "arrow")]
+        if let Some((format, decimals)) = columnar {
+            return columnar::write_batches(rx, writer, format, &decimals);
+        }
         let mut w = writer;
         for chunk in rx {
             w.write_all(&chunk)?;
         }
-        Ok(())
+        w.flush()
+    });
+
+    let mut summary = ParseSummary::default();
+    let parsed = send_header(options, &tx).and_then(|()| {
+        paths.iter().try_for_each(|path| {
+            let path = path.as_ref();
+            let s = parse_file(path, options, &tx).map_err(|e| e.in_file(path))?;
+            summary.skew_violations += s.skew_violations;
+            summary.diagnostics.merge(s.diagnostics.in_file(path));
+            Ok(())
+        })
     });
 
+    // Closing tx signals the printer thread to exit after draining.
+    drop(tx);
+    let printed = printer.join().expect("printer thread panicked");
+    // A write error on the printer side also surfaces here as a closed
+    // channel; report the printer's error since it names the real cause.
+    printed
+        .map_err(ParseError::Write)
+        .and(parsed)
+        .map(|()| summary)
+}
+
+/// Sends what comes before the first quote of a run: the CSV header row.
+fn send_header(options: &ParseOptions, tx: &mpsc::SyncSender<Vec<u8>>) -> Result<(), ParseError> {
+    match options.format {
+        OutputFormat::Csv {
+            delimiter,
+            header: true,
+        } if options.messages.is_none() => {
+            let mut header = Vec::new();
+            csv::write_header(&mut header, LineFormat::new(options), delimiter);
+            send_chunk(tx, header)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `options.threads`, or one worker per available core.
+fn worker_count(options: &ParseOptions) -> usize {
+    options
+        .threads
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, |n| n.get())
+}
+
+/// Maps a capture read-only for a front-to-back scan.
+fn map_file(path: &Path, populate: bool) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    let mut mmap_options = MmapOptions::new();
+    if populate {
+        mmap_options.populate();
+    }
+    let mmap = unsafe { mmap_options.map(&file)? };
+    let _ = mmap.advise(Advice::Sequential);
+    Ok(mmap)
+}
+
+fn parse_file(
+    path: &Path,
+    options: &ParseOptions,
+    tx: &mpsc::SyncSender<Vec<u8>>,
+) -> Result<ParseSummary, ParseError> {
+    // Pre-faulting pays off when the whole capture is indexed anyway; the
+    // streaming modes would rather start printing after the first window.
+    let mmap = map_file(path, options.ordering == PacketOrdering::QuoteAcceptTime)?;
+    let capture = Capture::parse(&mmap)?;
+
     // ── Worker chunk descriptors ─────────────────────────────────────────────
     // Each worker owns [base, own_end) and scans [base, scan_end) where the
     // extra OVERLAP bytes allow patterns straddling the split to be found.
     // A match at global position gpos is claimed by the worker that owns it:
     //   gpos ∈ [base, own_end) (gpos = global byte position of a found B6034)
     // The next worker starts at own_end, so no match is double-counted.
-    let nworkers = thread::available_parallelism()
-        .map(|n| n.get())
-        .unwrap_or(1);
-    let chunk_own = (file_len + nworkers - 1) / nworkers; // ceil(file_len / nworkers): bytes owned per worker
+    let nworkers = worker_count(options);
+    if let Some(registry) = &options.messages {
+        return print_messages(&mmap, &capture, options, registry, nworkers, tx);
+    }
     let cap_per_work = (16_004 * 180 / nworkers).max(1024); // ~16k rows * ~180 bytes, split per worker
+    let format = LineFormat::new(options);
+    let (start, end) = scan_range(&mmap, &capture, options);
 
-    match ordering {
-        //Print packets on packet time
+    match options.ordering {
+        // ── Default: windowed scan, printed in packet (file) order ──────────
+        // Workers format 1 MiB windows of the mmap and the main thread
+        // forwards each finished window to the printer in file order, so
+        // output starts after the first window and memory stays bounded by
+        // the windows in flight, however large the capture.
         PacketOrdering::Default => {
-            let results: Vec<Vec<u8>> = thread::scope(|s| {
-                let mmap = &mmap;
-                let handles: Vec<_> = (0..nworkers)
-                    .map(|i| {
-                        s.spawn(move || {
-                            let base = i * chunk_own;
-                            if base >= file_len {
-                                return Vec::new();
-                            }
-                            let own_end = (base + chunk_own).min(file_len);
-                            let scan_end = (own_end + OVERLAP).min(file_len);
-
-                            let u32_at = |j: usize| -> u32 {
-                                let b: [u8; 4] = mmap[j..j + 4].try_into().unwrap();
-                                if le {
-                                    u32::from_le_bytes(b)
-                                } else {
-                                    u32::from_be_bytes(b)
-                                }
-                            };
-
-                            let finder = memmem::Finder::new(b"B6034");
-                            let mut buf = Vec::with_capacity(cap_per_work);
-
-                            for local_pos in finder.find_iter(&mmap[base..scan_end]) {
-                                let gpos = base + local_pos;
-                                if gpos >= own_end {
-                                    break;
-                                }
-
-                                //The worker scans slightly past its owned range because of overlap,
-                                // but it only owns matches before `own_end`.
-                                // If the match is beyond that, stop.
-                                if gpos < HDR_TO_PAYLOAD {
-                                    continue;
-                                }
-                                //Avoid underflow.
-                                //To validate the pcap record, we later subtract `HDR_TO_PAYLOAD`,
-                                //so this match must be far enough into the file
-                                let rec = gpos - HDR_TO_PAYLOAD;
-
-                                //Compute the start offset of the pcap packet record.
-                                if u32_at(rec + 8) != RECORD_DATA_LEN {
-                                    continue;
-                                }
-                                if gpos + PAYLOAD_LEN > file_len {
-                                    continue;
-                                }
-                                write_quote(
-                                    &mut buf,
-                                    u32_at(rec),
-                                    u32_at(rec + 4),
-                                    &mmap[gpos..gpos + PAYLOAD_LEN],
-                                );
-                            }
-                            buf
-                        })
-                    })
-                    .collect();
-                handles.into_iter().map(|h| h.join().unwrap()).collect()
-            });
-
-            //Send the Packets in main thread buffer onto the printing thread buffer
-            for buf in results {
-                if !buf.is_empty() {
-                    tx.send(buf).map_err(|_| {
-                        io::Error::new(io::ErrorKind::BrokenPipe, "printer thread exited")
-                    })?;
-                }
-            }
+            let mut summary = ParseSummary::default();
+            run_windows(
+                nworkers,
+                start,
+                end,
+                |base, own_end| {
+                    let mut buf = Vec::with_capacity(WINDOW_BYTES);
+                    let mut diag = Diagnostics::new(options.diagnostics);
+                    let scanned = scan_quotes(
+                        &mmap,
+                        &capture,
+                        options,
+                        base,
+                        own_end,
+                        &mut diag,
+                        |ts_sec, ts_nsec, _, quote| {
+                            write_quote(&mut buf, format, ts_sec, ts_nsec, quote);
+                        },
+                    );
+                    (buf, diag, scanned)
+                },
+                |(buf, diag, scanned)| {
+                    summary.diagnostics.merge(diag);
+                    // A strict failure still prints the quotes before it.
+                    if !buf.is_empty() {
+                        send_chunk(tx, buf)?;
+                    }
+                    scanned
+                },
+            )?;
+            Ok(summary)
         }
 
         // ── QuoteAcceptTime: parallel scan + sort by accept-time ─────────────
@@ -290,91 +789,53 @@ pub fn read_pcap_file<W: Write + Send + 'static>(
         // and equals byte arrival-order, giving a deterministic tiebreaker.
         //
         PacketOrdering::QuoteAcceptTime => {
-            type WorkerOut = (Vec<u8>, Vec<QuoteAcceptIndex>);
-
-            let results: Vec<WorkerOut> = thread::scope(|s| {
-                let mmap = &mmap;
-                let handles: Vec<_> = (0..nworkers)
-                    .map(|i| {
-                        s.spawn(move || -> WorkerOut {
-                            //Multiplying so that we can get the ith chunk for the ith worker
-                            let base = i * chunk_own;
-                            if base >= file_len {
-                                return (Vec::new(), Vec::new());
-                            }
-                            //If there is no file data for this worker, return empty output and empty index.
-                            let own_end = (base + chunk_own).min(file_len);
-                            //scan_end` extends slightly past that by `OVERLAP`,
-                            // so the worker can still see a `B6034` marker crossing a chunk boundary.
-                            let scan_end = (own_end + OVERLAP).min(file_len);
-
-                            //Helper to read a 4-byte integer from the mmap using the pcap file’s endianness.
-                            let u32_at = |j: usize| -> u32 {
-                                let b: [u8; 4] = mmap[j..j + 4].try_into().unwrap();
-                                if le {
-                                    u32::from_le_bytes(b)
-                                } else {
-                                    u32::from_be_bytes(b)
-                                }
-                            };
+            let mut results = scan_chunks(nworkers, start, end, |base, own_end| {
+                let mut buf = Vec::with_capacity(cap_per_work);
+                //preallocate index for roughly
+                //(number of output bytes / average row size) + small headroom
+                let mut index = Vec::with_capacity(cap_per_work / 180 + 16);
+                let mut diag = Diagnostics::new(options.diagnostics);
 
-                            let finder = memmem::Finder::new(b"B6034");
-                            let mut buf = Vec::with_capacity(cap_per_work);
-                            //preallocate index for roughly
-                            //(number of output bytes / average row size) + small headroom
-                            let mut index = Vec::with_capacity(cap_per_work / 180 + 16);
-
-                            //Find every `B6034` in this worker’s scan range.
-                            for local_pos in finder.find_iter(&mmap[base..scan_end]) {
-                                let gpos = base + local_pos;
-                                if gpos >= own_end {
-                                    break;
-                                }
-                                //Ignore overlap matches owned by the next worker.
-                                if gpos < HDR_TO_PAYLOAD {
-                                    continue;
-                                }
-                                //Avoid underflow before subtracting header size.
-                                let rec = gpos - HDR_TO_PAYLOAD;
-                                //Compute start of the pcap packet record.
-                                if u32_at(rec + 8) != RECORD_DATA_LEN {
-                                    continue;
-                                }
-                                //Validate captured packet length, filtering false `B6034` matches.
-                                if gpos + PAYLOAD_LEN > file_len {
-                                    continue;
-                                }
-                                let start = buf.len() as u32;
-                                //Remember where this row starts inside the worker buffer.
-                                let key = write_quote(
-                                    &mut buf,
-                                    u32_at(rec),
-                                    u32_at(rec + 4),
-                                    &mmap[gpos..gpos + PAYLOAD_LEN],
-                                );
-                                let len = buf.len() as u32 - start;
-                                index.push(QuoteAcceptIndex {
-                                    accept_time_cs: key,
-                                    global_pos: gpos as u32,
-                                    local_start: start,
-                                    local_len: len,
-                                });
-                            }
-                            (buf, index)
-                        })
-                    })
-                    .collect();
-                handles.into_iter().map(|h| h.join().unwrap()).collect()
+                let scanned = scan_quotes(
+                    &mmap,
+                    &capture,
+                    options,
+                    base,
+                    own_end,
+                    &mut diag,
+                    |ts_sec, ts_nsec, gpos, quote| {
+                        //Remember where this row starts inside the worker buffer.
+                        let start = buf.len();
+                        let key = write_quote(&mut buf, format, ts_sec, ts_nsec, quote);
+                        let len = buf.len() - start;
+                        index.push(QuoteAcceptIndex {
+                            accept_time_cs: key,
+                            global_pos: gpos as u64,
+                            local_start: start,
+                            local_len: len,
+                        });
+                    },
+                );
+                (buf, index, diag, scanned)
             });
 
-            //At this point, each worker has returned: (Vec<u8>, Vec<QuoteAcceptIndex>)
+            //At this point, each worker has returned: (Vec<u8>, Vec<QuoteAcceptIndex>, Diagnostics, Result)
             //`Vec<u8>` = that worker’s formatted rows
             //`Vec<QuoteAcceptIndex>` = row metadata pointing into that worker’s buffer
+            //`Result` = the scan's strict validation failure, if any
+            //
+            // After a failure only the quotes before it are sorted: the
+            // failing worker kept its rows up to it, later workers' go.
+            let failed = results.iter().position(|(.., scanned)| scanned.is_err());
+            results.truncate(failed.map_or(results.len(), |i| i + 1));
+            let scanned = failed.map_or(Ok(()), |i| mem::replace(&mut results[i].3, Ok(())));
 
             //Collect all indexes of each worker
-            let total_idx: usize = results.iter().map(|(_, idx)| idx.len()).sum();
+            let mut summary = ParseSummary::default();
+            let total_idx: usize = results.iter().map(|(_, idx, ..)| idx.len()).sum();
             let mut all_idx: Vec<MergedQuoteAcceptIndex> = Vec::with_capacity(total_idx);
-            for (tid, (_, idx)) in results.iter().enumerate() {
+            for (tid, (_, idx, diag, _)) in results.iter_mut().enumerate() {
+                summary.diagnostics.merge(mem::take(diag));
                 for item in idx {
                     all_idx.push(MergedQuoteAcceptIndex {
                         accept_time_cs: item.accept_time_cs,
@@ -392,24 +853,581 @@ pub fn read_pcap_file<W: Write + Send + 'static>(
             let mut flush_buf: Vec<u8> = Vec::with_capacity(CHUNK_BYTES + 256);
             for item in &all_idx {
                 let src = &results[item.worker_id].0;
-                let start = item.local_start as usize;
-                let end = start + item.local_len as usize;
+                let start = item.local_start;
+                let end = start + item.local_len;
                 flush_buf.extend_from_slice(&src[start..end]);
                 if flush_buf.len() >= CHUNK_BYTES {
-                    flush_chunk(&tx, &mut flush_buf)?;
+                    flush_chunk(tx, &mut flush_buf)?;
                 }
             }
 
             //Final chunk
             if !flush_buf.is_empty() {
-                tx.send(flush_buf).map_err(|_| {
-                    io::Error::new(io::ErrorKind::BrokenPipe, "printer thread exited")
-                })?;
+                send_chunk(tx, flush_buf)?;
             }
+            scanned.map(|()| summary)
+        }
+
+        // ── StreamingQuoteAcceptTime: windowed scan + bounded min-heap ───────
+        // Windows of the mmap are formatted in parallel and handed back in
+        // file order.  Every row goes into a min-heap keyed on
+        // (accept_time_cs, global_pos); the packet-time watermark only moves
+        // forward, and no later packet may carry an accept time older than
+        // watermark - max_skew_cs, so everything at or below that is final.
+        //
+        // A window's buffer is kept until its last row has been emitted, so
+        // memory stays proportional to the skew bound, not the capture.  A
+        // row whose accept time is beyond its packet time plus the bound
+        // would never be released in time, so it is emitted as soon as its
+        // window arrives instead of pinning the window until EOF.
+        PacketOrdering::StreamingQuoteAcceptTime => {
+            let max_skew_cs = options.max_skew_cs;
+            let mut summary = ParseSummary::default();
+            let mut heap: BinaryHeap<Reverse<MergedQuoteAcceptIndex>> = BinaryHeap::new();
+            // Window buffers with their count of not-yet-emitted rows; the
+            // front entry is window number `first_window`. A buffer is freed
+            // as soon as its count reaches 0, and the entry once every window
+            // before it is done too.
+            let mut windows: VecDeque<(Vec<u8>, usize)> = VecDeque::new();
+            let mut first_window = 0usize;
+            let mut watermark_cs = 0u32;
+            let mut flush_buf: Vec<u8> = Vec::with_capacity(CHUNK_BYTES + 256);
+
+            // Emit every queued row whose accept time is at or below `limit_cs`.
+            let drain = |heap: &mut BinaryHeap<Reverse<MergedQuoteAcceptIndex>>,
+                         windows: &mut VecDeque<(Vec<u8>, usize)>,
+                         first_window: &mut usize,
+                         flush_buf: &mut Vec<u8>,
+                         limit_cs: u32|
+             -> Result<(), ParseError> {
+                while let Some(&Reverse(item)) = heap.peek() {
+                    if item.accept_time_cs > limit_cs {
+                        break;
+                    }
+                    heap.pop();
+                    let (src, pending) = &mut windows[item.worker_id - *first_window];
+                    let start = item.local_start;
+                    let end = start + item.local_len;
+                    flush_buf.extend_from_slice(&src[start..end]);
+                    *pending -= 1;
+                    if *pending == 0 {
+                        *src = Vec::new();
+                    }
+                    if flush_buf.len() >= CHUNK_BYTES {
+                        flush_chunk(tx, flush_buf)?;
+                    }
+                }
+                while windows.front().is_some_and(|(_, pending)| *pending == 0) {
+                    windows.pop_front();
+                    *first_window += 1;
+                }
+                Ok(())
+            };
+
+            let mut window_id = 0usize;
+            let scanned = run_windows(
+                nworkers,
+                start,
+                end,
+                |base, own_end| {
+                    let mut out = StreamWindow {
+                        buf: Vec::with_capacity(WINDOW_BYTES),
+                        index: Vec::new(),
+                        early: Vec::new(),
+                        watermark_cs: 0,
+                        skew_violations: 0,
+                        diagnostics: Diagnostics::new(options.diagnostics),
+                    };
+                    let scanned = scan_quotes(
+                        &mmap,
+                        &capture,
+                        options,
+                        base,
+                        own_end,
+                        &mut out.diagnostics,
+                        |ts_sec, ts_nsec, gpos, quote| {
+                            let start = out.buf.len();
+                            let key = write_quote(&mut out.buf, format, ts_sec, ts_nsec, quote);
+                            let packet_cs = packet_time_cs(ts_sec, ts_nsec);
+                            let early = key > packet_cs.saturating_add(max_skew_cs);
+                            if early || packet_cs.saturating_sub(key) > max_skew_cs {
+                                out.skew_violations += 1;
+                            }
+                            out.watermark_cs = out.watermark_cs.max(packet_cs);
+                            let item = QuoteAcceptIndex {
+                                accept_time_cs: key,
+                                global_pos: gpos as u64,
+                                local_start: start,
+                                local_len: out.buf.len() - start,
+                            };
+                            if early {
+                                out.early.push(item);
+                            } else {
+                                out.index.push(item);
+                            }
+                        },
+                    );
+                    (out, scanned)
+                },
+                |(out, scanned)| {
+                    summary.skew_violations += out.skew_violations;
+                    summary.diagnostics.merge(out.diagnostics);
+                    for item in &out.early {
+                        let start = item.local_start;
+                        let end = start + item.local_len;
+                        flush_buf.extend_from_slice(&out.buf[start..end]);
+                    }
+                    if flush_buf.len() >= CHUNK_BYTES {
+                        flush_chunk(tx, &mut flush_buf)?;
+                    }
+                    // `worker_id` names the window here: rows are looked up
+                    // in `windows` rather than in per-worker buffers.
+                    for item in &out.index {
+                        heap.push(Reverse(MergedQuoteAcceptIndex {
+                            accept_time_cs: item.accept_time_cs,
+                            global_pos: item.global_pos,
+                            worker_id: window_id,
+                            local_start: item.local_start,
+                            local_len: item.local_len,
+                        }));
+                    }
+                    let buf = if out.index.is_empty() {
+                        Vec::new()
+                    } else {
+                        out.buf
+                    };
+                    windows.push_back((buf, out.index.len()));
+                    window_id += 1;
+                    watermark_cs = watermark_cs.max(out.watermark_cs);
+                    if let Some(limit) = watermark_cs.checked_sub(max_skew_cs) {
+                        drain(
+                            &mut heap,
+                            &mut windows,
+                            &mut first_window,
+                            &mut flush_buf,
+                            limit,
+                        )?;
+                    }
+                    scanned
+                },
+            );
+
+            // End of capture, or of the quotes before a strict failure:
+            // nothing later will arrive, flush the rest.
+            drain(
+                &mut heap,
+                &mut windows,
+                &mut first_window,
+                &mut flush_buf,
+                u32::MAX,
+            )?;
+            if !flush_buf.is_empty() {
+                send_chunk(tx, flush_buf)?;
+            }
+            scanned.map(|()| summary)
         }
     }
+}
 
-    // Closing tx signals the printer thread to exit after draining.
-    drop(tx);
-    printer.join().expect("printer thread panicked")
+/// Prints every message of the capture that `registry` decodes, in file
+/// order, with the windowed pipeline of the default ordering. A packet time
+/// range skips the records outside it.
+fn print_messages(
+    mmap: &[u8],
+    capture: &Capture,
+    options: &ParseOptions,
+    registry: &MessageRegistry,
+    nworkers: usize,
+    tx: &mpsc::SyncSender<Vec<u8>>,
+) -> Result<ParseSummary, ParseError> {
+    let precision = options.precision;
+    let range = options.time_range;
+    let (start, end) = scan_range(mmap, capture, options);
+    let mut summary = ParseSummary::default();
+    run_windows(
+        nworkers,
+        start,
+        end,
+        |base, own_end| -> Result<_, ParseError> {
+            capture.check_range(mmap, base, own_end)?;
+            let mut buf = Vec::with_capacity(WINDOW_BYTES);
+            let mut diag = Diagnostics::new(options.diagnostics);
+            let on_payload = |diag: &mut Diagnostics, ts_sec, ts_nsec, pos, payload: &[u8]| {
+                if !range.contains_packet(ts_sec, ts_nsec) {
+                    return ControlFlow::Continue(());
+                }
+                for (offset, message) in registry.messages(payload) {
+                    let Some(decoder) = registry.decoder(message) else {
+                        continue;
+                    };
+                    let start = buf.len();
+                    write_packet_time(&mut buf, precision, ts_sec, ts_nsec);
+                    buf.push(b' ');
+                    buf.extend_from_slice(&message[..message.len().min(feed::HEADER_LEN)]);
+                    buf.push(b' ');
+                    if decoder.write_fields(message, &mut buf) {
+                        buf.push(b'\n');
+                    } else {
+                        buf.truncate(start);
+                        diag.skip(|| ParseError::MalformedMessage {
+                            offset: (pos + offset) as u64,
+                        });
+                    }
+                }
+                ControlFlow::Continue(())
+            };
+            scan_structural(
+                mmap,
+                capture,
+                options.defrag,
+                base,
+                own_end,
+                &mut diag,
+                on_payload,
+            );
+            Ok((buf, diag))
+        },
+        |window| {
+            let (buf, diag) = window?;
+            summary.diagnostics.merge(diag);
+            if buf.is_empty() {
+                return Ok(());
+            }
+            send_chunk(tx, buf)
+        },
+    )?;
+    Ok(summary)
+}
+
+/// Calls `f(ts_sec, ts_nsec, global_pos, quote)` for every valid quote
+/// owned by `[base, own_end)`; `global_pos` is the quote's offset in the
+/// capture, which orders quotes by arrival. Records skipped on the way
+/// are reported to `diag`.
+///
+/// Quotes of issues `options.issues` does not keep, or outside
+/// `options.time_range`, are skipped first, then
+/// the rest are checked as `options.validation` asks; with `Strict` the
+/// first invalid one stops the scan, and the error is returned after `f`
+/// has seen every quote before it.
+#[inline]
+fn scan_quotes(
+    mmap: &[u8],
+    capture: &Capture,
+    options: &ParseOptions,
+    base: usize,
+    own_end: usize,
+    diag: &mut Diagnostics,
+    mut f: impl FnMut(u32, u32, usize, Quote),
+) -> Result<(), ParseError> {
+    capture.check_range(mmap, base, own_end)?;
+    let validation = options.validation;
+    let markets = options.market_types;
+    let issues = (!options.issues.is_empty()).then_some(&options.issues);
+    let range = (!options.time_range.is_all()).then_some(options.time_range);
+    let mut failed = None;
+    let visit = |diag: &mut Diagnostics, ts_sec, ts_nsec, gpos: usize, quote: Quote| {
+        if issues.is_some_and(|issues| !issues.matches(quote.issue_code())) {
+            return ControlFlow::Continue(());
+        }
+        if range.is_some_and(|range| !range.contains(ts_sec, ts_nsec, &quote)) {
+            return ControlFlow::Continue(());
+        }
+        if validation != Validation::Off {
+            if let Err(field) = quote.validate() {
+                let error = ParseError::InvalidField {
+                    offset: gpos as u64,
+                    field,
+                };
+                if validation == Validation::Strict {
+                    failed = Some(error);
+                    return ControlFlow::Break(());
+                }
+                diag.reject(error);
+                return ControlFlow::Continue(());
+            }
+        }
+        f(ts_sec, ts_nsec, gpos, quote);
+        ControlFlow::Continue(())
+    };
+    match options.scan {
+        ScanMode::Pattern => scan_pattern(mmap, capture, markets, base, own_end, diag, visit),
+        ScanMode::Structural => {
+            let mut visit = visit;
+            let on_payload = |diag: &mut Diagnostics, ts_sec, ts_nsec, pos, payload: &[u8]| {
+                feed_quotes(payload, pos, markets, diag, |diag, gpos, quote| {
+                    visit(diag, ts_sec, ts_nsec, gpos, quote)
+                })
+            };
+            scan_structural(
+                mmap,
+                capture,
+                options.defrag,
+                base,
+                own_end,
+                diag,
+                on_payload,
+            )
+        }
+    }
+    failed.map_or(Ok(()), Err)
+}
+
+/// Structural scan: a range owns the records whose header starts inside it,
+/// and the fragmented datagrams whose first fragment (in file order) does.
+///
+/// Past `own_end` the walk continues only to complete datagrams it already
+/// holds fragments of, for at most `DEFRAG_LOOKAHEAD_BYTES`. So a datagram
+/// first seen within that distance of `base` may belong to the range
+/// before, which finishes or reports it; here it is dropped unreported.
+///
+/// Calls `f(diag, ts_sec, ts_nsec, pos, payload)` for the UDP payload of
+/// every datagram sent to a feed port, `pos` being where the payload
+/// starts, until it breaks. A reassembled datagram takes the time of the
+/// fragment that completed it and is placed after that fragment's data.
+fn scan_structural(
+    mmap: &[u8],
+    capture: &Capture,
+    defrag: DefragOptions,
+    base: usize,
+    own_end: usize,
+    diag: &mut Diagnostics,
+    mut f: impl FnMut(&mut Diagnostics, u32, u32, usize, &[u8]) -> ControlFlow<()>,
+) {
+    let mut reassembler = Reassembler::new(defrag);
+    let unowned_end = if base > capture.data_start() {
+        base + DEFRAG_LOOKAHEAD_BYTES
+    } else {
+        base
+    };
+    let report_dropped = |reassembler: &mut Reassembler, diag: &mut Diagnostics| {
+        for offset in reassembler.take_dropped() {
+            if offset >= unowned_end {
+                diag.skip(|| ParseError::IncompleteDatagram {
+                    offset: offset as u64,
+                });
+            }
+        }
+    };
+    capture.walk_records(mmap, base, own_end, |record| {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                diag.skip(|| e);
+                return ControlFlow::Continue(());
+            }
+        };
+        let owned = record.start < own_end;
+        let now_ns = record.ts_sec as u64 * 1_000_000_000 + record.ts_nsec as u64;
+        if !owned {
+            reassembler.expire(now_ns);
+            if reassembler.is_empty() || record.start - own_end > DEFRAG_LOOKAHEAD_BYTES {
+                return ControlFlow::Break(());
+            }
+        }
+        let flow = match net::decode(record.linktype, record.frame) {
+            Some(net::Packet::Datagram(dgram))
+                if owned && net::QUOTE_PORTS.contains(&dgram.dst_port) =>
+            {
+                let pos = record.frame_pos + dgram.offset;
+                f(diag, record.ts_sec, record.ts_nsec, pos, dgram.payload)
+            }
+            Some(net::Packet::Fragment(frag)) => {
+                let frag_pos = record.frame_pos + frag.frame_offset;
+                let completed = reassembler.insert(&frag, frag_pos, now_ns, owned);
+                let dgram = completed.as_deref().and_then(net::reassembled_udp);
+                match dgram.filter(|d| net::QUOTE_PORTS.contains(&d.dst_port)) {
+                    // Positions only need to be unique and increasing, so the
+                    // payload is placed after the completing fragment's data.
+                    Some(dgram) => f(diag, record.ts_sec, record.ts_nsec, frag_pos, dgram.payload),
+                    None => ControlFlow::Continue(()),
+                }
+            }
+            _ => ControlFlow::Continue(()),
+        };
+        report_dropped(&mut reassembler, diag);
+        flow
+    });
+    reassembler.drop_all();
+    report_dropped(&mut reassembler, diag);
+}
+
+/// Calls `f(diag, global_pos, quote)` for each quote of one of `markets`
+/// in a feed payload that starts at `pos`, until it breaks.
+#[inline]
+fn feed_quotes<'a>(
+    payload: &'a [u8],
+    pos: usize,
+    markets: MarketTypes,
+    diag: &mut Diagnostics,
+    mut f: impl FnMut(&mut Diagnostics, usize, Quote<'a>) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    for (offset, message) in feed::messages(payload) {
+        let market_type = message.get(QUOTE_PREFIX.len());
+        if !message.starts_with(QUOTE_PREFIX) || !market_type.is_some_and(|&t| markets.contains(t))
+        {
+            continue;
+        }
+        match Quote::new(message) {
+            Some(quote) => f(diag, pos + offset, quote)?,
+            None => diag.skip(|| ParseError::BadMessageLength {
+                offset: (pos + offset) as u64,
+                len: message.len(),
+            }),
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+/// Pattern scan: a match is owned by the range holding its first byte.
+/// Calls `f(diag, ts_sec, ts_nsec, global_pos, quote)` for each quote of
+/// one of `markets`, until it breaks.
+///
+/// The scan runs `OVERLAP` bytes past `own_end` so a marker straddling the
+/// split is still seen by the range that owns its first byte; the next range
+/// starts at `own_end`, so no match is reported twice.
+///
+/// A quote sharing its datagram with other messages needs a search for the
+/// enclosing record; the payload found is remembered, so the datagram's
+/// later quotes are checked against it directly.
+#[inline]
+fn scan_pattern(
+    mmap: &[u8],
+    capture: &Capture,
+    markets: MarketTypes,
+    base: usize,
+    own_end: usize,
+    diag: &mut Diagnostics,
+    mut f: impl FnMut(&mut Diagnostics, u32, u32, usize, Quote) -> ControlFlow<()>,
+) {
+    let scan_end = (own_end + OVERLAP).min(mmap.len());
+
+    // A single market type is searched for with its whole header; several
+    // share the `B603` prefix and are told apart by the byte after it.
+    let single = markets.single().map(|t| [&QUOTE_PREFIX[..], &[t]].concat());
+    let finder = memmem::Finder::new(single.as_deref().unwrap_or(QUOTE_PREFIX));
+    let mut batch: Option<capture::Payload> = None;
+    for local_pos in finder.find_iter(&mmap[base..scan_end]) {
+        let gpos = base + local_pos;
+        //The range scans slightly past its owned bytes because of overlap,
+        // but it only owns matches before `own_end`.
+        if gpos >= own_end {
+            break;
+        }
+        if !mmap
+            .get(gpos + QUOTE_PREFIX.len())
+            .is_some_and(|&t| markets.contains(t))
+        {
+            continue;
+        }
+        //Validate the enclosing record, filtering false `B6034` matches.
+        let stray = || ParseError::StrayMarker {
+            offset: gpos as u64,
+        };
+        let (ts_sec, ts_nsec) = match batch.filter(|p| (p.start..p.end).contains(&gpos)) {
+            Some(p) => {
+                let payload = &mmap[p.start..p.end];
+                if !feed::quotes(payload).any(|(offset, _)| p.start + offset == gpos) {
+                    diag.skip(stray);
+                    continue;
+                }
+                (p.ts_sec, p.ts_nsec)
+            }
+            None => match capture.quote_timestamp(mmap, gpos) {
+                Some(ts) => ts,
+                None => {
+                    let Some(p) = capture.enclosing_payload(mmap, gpos) else {
+                        diag.skip(stray);
+                        continue;
+                    };
+                    batch = Some(p);
+                    (p.ts_sec, p.ts_nsec)
+                }
+            },
+        };
+        let quote = Quote::new(&mmap[gpos..gpos + QUOTE_LEN]);
+        if quote.is_some_and(|quote| f(diag, ts_sec, ts_nsec, gpos, quote).is_break()) {
+            break;
+        }
+    }
+}
+
+/// The part of the capture a scan has to read: all of it, unless a packet
+/// time range lets `Capture::seek` skip records before and after it.
+fn scan_range(mmap: &[u8], capture: &Capture, options: &ParseOptions) -> (usize, usize) {
+    let range = options.time_range;
+    if range.field != TimeField::Packet || range.is_all() {
+        return (capture.data_start(), mmap.len());
+    }
+    let before = |cs| move |ts_sec, ts_nsec| packet_time_cs(ts_sec, ts_nsec) < cs;
+    let start = capture.seek(mmap, before(range.from_cs));
+    let end = capture.seek(mmap, before(range.to_cs));
+    (start, end.max(start))
+}
+
+/// Splits `[start, end)` into one contiguous chunk per worker, runs
+/// `produce(base, own_end)` for each on its own thread and returns the
+/// results in file order. Workers past the end of a small file get no chunk.
+fn scan_chunks<T, P>(nworkers: usize, start: usize, end: usize, produce: P) -> Vec<T>
+where
+    T: Send,
+    P: Fn(usize, usize) -> T + Sync,
+{
+    let chunk_own = end.saturating_sub(start).div_ceil(nworkers).max(1); // bytes owned per worker
+    thread::scope(|s| {
+        let produce = &produce;
+        let handles: Vec<_> = (start..end)
+            .step_by(chunk_own)
+            .map(|base| s.spawn(move || produce(base, (base + chunk_own).min(end))))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+/// Splits `[start, end)` into `WINDOW_BYTES` windows, runs `produce(base,
+/// own_end)` for each on `nworkers` threads and passes the results to
+/// `consume` strictly in window order.
+///
+/// Window `k` goes to worker `k % nworkers`, and each worker has its own
+/// small bounded channel, so receiving round-robin restores file order
+/// without a reorder buffer and no worker gets more than
+/// `WINDOWS_IN_FLIGHT` windows ahead of the consumer.
+fn run_windows<T, P, C>(
+    nworkers: usize,
+    start: usize,
+    end: usize,
+    produce: P,
+    mut consume: C,
+) -> Result<(), ParseError>
+where
+    T: Send,
+    P: Fn(usize, usize) -> T + Sync,
+    C: FnMut(T) -> Result<(), ParseError>,
+{
+    let nwindows = end.saturating_sub(start).div_ceil(WINDOW_BYTES);
+    thread::scope(|s| {
+        let produce = &produce;
+        let rxs: Vec<mpsc::Receiver<T>> = (0..nworkers)
+            .map(|i| {
+                let (wtx, wrx) = mpsc::sync_channel(WINDOWS_IN_FLIGHT);
+                s.spawn(move || {
+                    for k in (i..nwindows).step_by(nworkers) {
+                        let base = start + k * WINDOW_BYTES;
+                        let own_end = (base + WINDOW_BYTES).min(end);
+                        // The consumer hung up after an error; stop early.
+                        if wtx.send(produce(base, own_end)).is_err() {
+                            break;
+                        }
+                    }
+                });
+                wrx
+            })
+            .collect();
+
+        // Returning early drops `rxs`, which unblocks and stops every worker.
+        for k in 0..nwindows {
+            let out = rxs[k % nworkers].recv().expect("worker thread panicked");
+            consume(out)?;
+        }
+        Ok(())
+    })
 }
@@ -1,20 +1,474 @@
-use kopsi_200_pcap_parser::{read_pcap_file, PacketOrdering, PCAP_FILE_PATH};
+use kopsi_200_pcap_parser::{
+    read_pcap_files, MarketTypes, MessageRegistry, NumberFormat, OutputFormat, OutputMode,
+    PacketOrdering, ParseError, ParseOptions, ParseSummary, ScanMode, TimeField, TimePrecision,
+    Validation, MAX_DECIMALS,
+};
 use std::{
     env,
-    io::{self, BufWriter},
+    fs::File,
+    io::{self, BufWriter, Write},
+    num::NonZeroUsize,
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
 };
 
-fn main() -> io::Result<()> {
-    let ordering = if env::args().any(|arg| arg == "-r") {
-        PacketOrdering::QuoteAcceptTime
-    } else {
-        PacketOrdering::Default
+const NAME: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The `--format` lines of `USAGE`, naming only the formats built in.
+#[cfg(feature = "parquet")]
+macro_rules! format_help {
+    () => {
+        "      --format <FORMAT>  Output format: text, csv with one column per field,
+                         jsonl with one JSON object per quote, or every
+                         field as a typed column: arrow (an IPC file),
+                         arrow-stream or parquet [default: text]
+"
+    };
+}
+#[cfg(all(feature = "arrow", not(feature = "parquet")))]
+macro_rules! format_help {
+    () => {
+        "      --format <FORMAT>  Output format: text, csv with one column per field,
+                         jsonl with one JSON object per quote, or every
+                         field as a typed column: arrow (an IPC file) or
+                         arrow-stream [default: text]
+"
+    };
+}
+#[cfg(not(feature = "arrow"))]
+macro_rules! format_help {
+    () => {
+        "      --format <FORMAT>  Output format: text, csv with one column per field,
+                         or jsonl with one JSON object per quote
+                         [default: text]
+"
+    };
+}
+
+const USAGE: &str = concat!(
+    "\
+Usage: kopsi-200-pcap-parser [OPTIONS] <PCAP>...
+
+Prints the B6034 quote messages found in each capture, in file order.
+
+Arguments:
+  <PCAP>...              Capture files to parse, processed in the given order
+
+Options:
+  -r                     Order quotes by quote accept time instead of packet time
+      --max-skew <SECS>  Packet/accept time skew bound used by -r [default: 3]
+      --full-sort        With -r, sort the whole capture at once instead of
+                         streaming (exact even if the skew bound is violated)
+  -o, --output <FILE>    Write quotes to FILE instead of stdout
+      --precision <P>    Packet time precision: ms, us or ns [default: ms]
+      --extended         Also print the issue seq no, market status, total
+                         volumes and valid quote counts
+      --from <TIME>      Keep quotes from this KST time of day, HH:MM[:SS[.ff]]
+      --to <TIME>        Keep quotes before this KST time of day
+      --range-on <CLOCK> Time --from/--to bound: packet, which skips the rest
+                         of the capture unread, or accept [default: packet]
+",
+    format_help!(),
+    "      --delimiter <CHAR> CSV field delimiter: tab, space, or an ASCII
+                         punctuation character other than . : and \"
+                         [default: ,]
+      --no-header        Leave out the CSV header row
+      --numeric          Print numbers without zero padding and prices with
+                         their implied decimals
+      --decimals <[PREFIX=]N>
+                         Implied price decimals for --numeric, for every
+                         issue or those whose code starts with PREFIX;
+                         repeatable [default: 2]
+      --messages         Print every feed message, not just quotes: B6034
+                         decoded, other types as raw text (implies
+                         --structural; only --from/--to of the filters
+                         apply, and not with -r)
+      --market <TYPES>   Market types to keep: one (4 = futures), a comma
+                         separated list, or all; unless one, each text line
+                         shows the market type before the issue code (CSV
+                         and JSON always carry it) [default: 4]
+      --issue <CODES>    Keep only these issue codes: a comma separated list
+                         of codes, prefixes ending in * or globs with * and
+                         ?; repeatable [default: all]
+      --issue-file <FILE>
+                         Also keep the issue codes listed in FILE, one
+                         pattern per line; # starts a comment
+      --structural       Walk every record and decode its headers instead of
+                         searching for the B6034 marker
+      --threads <N>      Number of worker threads [default: all cores]
+      --validate <MODE>  Check quote fields: strict stops at the first invalid
+                         quote, lenient skips and counts them [default: off]
+      --diagnostics      Report skipped records and why on stderr
+  -h, --help             Print this help and exit
+  -V, --version          Print the version and exit
+"
+);
+
+#[cfg(feature = "parquet")]
+const FORMATS: &str = "text, csv, jsonl, arrow, arrow-stream or parquet";
+#[cfg(all(feature = "arrow", not(feature = "parquet")))]
+const FORMATS: &str = "text, csv, jsonl, arrow or arrow-stream";
+#[cfg(not(feature = "arrow"))]
+const FORMATS: &str = "text, csv or jsonl";
+
+/// Output buffer size; large enough that the printer rarely hits a syscall.
+const OUT_BUF_BYTES: usize = 3 * 1024 * 1024;
+
+struct Args {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    /// `--issue-file`s, read when the run starts.
+    issue_files: Vec<PathBuf>,
+    options: ParseOptions,
+}
+
+enum Command {
+    Run(Box<Args>),
+    Help,
+    Version,
+}
+
+fn parse_args(mut argv: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut issue_files = Vec::new();
+    let mut options = ParseOptions::default();
+    let mut only_inputs = false;
+    let mut reorder = false;
+    let mut full_sort = false;
+    let mut format = "text".to_owned();
+    let mut delimiter = b',';
+    let mut header = true;
+    let mut markets = false;
+
+    while let Some(arg) = argv.next() {
+        if only_inputs || !arg.starts_with('-') {
+            inputs.push(PathBuf::from(arg));
+            continue;
+        }
+
+        // Accept both `--flag value` and `--flag=value` for long options.
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if f.starts_with("--") => (f.to_owned(), Some(v.to_owned())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline
+                .clone()
+                .or_else(|| argv.next())
+                .ok_or_else(|| format!("{name} requires a value"))
+        };
+
+        match flag.as_str() {
+            "--" => only_inputs = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-r" => reorder = true,
+            "--full-sort" => full_sort = true,
+            "--structural" => options.scan = ScanMode::Structural,
+            "--extended" => options.output = OutputMode::Extended,
+            "--messages" => options.messages = Some(Arc::new(MessageRegistry::kospi200())),
+            "--market" => {
+                markets = true;
+                let types = value(&flag)?;
+                options.market_types = parse_market_types(&types)
+                    .ok_or_else(|| format!("invalid market types '{types}'"))?;
+            }
+            "--issue" => {
+                let codes = value(&flag)?;
+                if codes.split(',').any(|code| code.trim().is_empty()) {
+                    return Err(format!("invalid issue codes '{codes}'"));
+                }
+                for code in codes.split(',') {
+                    options.issues.add(code);
+                }
+            }
+            "--issue-file" => issue_files.push(PathBuf::from(value(&flag)?)),
+            "--from" | "--to" => {
+                let time = value(&flag)?;
+                let cs =
+                    parse_time_cs(&time).ok_or_else(|| format!("invalid time of day '{time}'"))?;
+                match flag.as_str() {
+                    "--from" => options.time_range.from_cs = cs,
+                    _ => options.time_range.to_cs = cs,
+                }
+            }
+            "--range-on" => {
+                options.time_range.field = match value(&flag)?.as_str() {
+                    "packet" => TimeField::Packet,
+                    "accept" => TimeField::Accept,
+                    c => return Err(format!("invalid clock '{c}', expected packet or accept")),
+                };
+            }
+            "--format" => format = value(&flag)?,
+            "--delimiter" => {
+                let d = value(&flag)?;
+                delimiter =
+                    parse_delimiter(&d).ok_or_else(|| format!("invalid delimiter '{d}'"))?;
+            }
+            "--no-header" => header = false,
+            "--numeric" => options.numbers = NumberFormat::Decoded,
+            "--decimals" => {
+                let spec = value(&flag)?;
+                let (prefix, n) = match spec.split_once('=') {
+                    Some((prefix, n)) => (Some(prefix), n),
+                    None => (None, spec.as_str()),
+                };
+                let n = n
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|&n| n <= MAX_DECIMALS)
+                    .ok_or_else(|| format!("invalid decimals '{spec}'"))?;
+                let decimals = std::mem::take(&mut options.price_decimals);
+                options.price_decimals = match prefix {
+                    Some(prefix) => decimals.with_prefix(prefix, n),
+                    None => decimals.with_default(n),
+                };
+            }
+            "--diagnostics" => options.diagnostics = true,
+            "--max-skew" => {
+                let secs = value(&flag)?;
+                options.max_skew_cs =
+                    parse_skew_cs(&secs).ok_or_else(|| format!("invalid skew bound '{secs}'"))?;
+            }
+            "--precision" => {
+                options.precision = match value(&flag)?.as_str() {
+                    "ms" => TimePrecision::Millis,
+                    "us" | "µs" => TimePrecision::Micros,
+                    "ns" => TimePrecision::Nanos,
+                    p => return Err(format!("invalid precision '{p}', expected ms, us or ns")),
+                };
+            }
+            "--validate" => {
+                options.validation = match value(&flag)?.as_str() {
+                    "off" => Validation::Off,
+                    "lenient" => Validation::Lenient,
+                    "strict" => Validation::Strict,
+                    m => {
+                        return Err(format!(
+                            "invalid validation mode '{m}', expected off, lenient or strict"
+                        ))
+                    }
+                };
+            }
+            "-o" | "--output" => output = Some(parse_output(&flag, &value(&flag)?)?),
+            "--threads" => {
+                let n = value(&flag)?;
+                let n = n
+                    .parse::<NonZeroUsize>()
+                    .map_err(|_| format!("invalid thread count '{n}'"))?;
+                options.threads = Some(n);
+            }
+            // `-oFILE` and `-o=FILE`.
+            _ if flag.starts_with("-o") => {
+                let path = &flag[2..];
+                output = Some(parse_output("-o", path.strip_prefix('=').unwrap_or(path))?);
+            }
+            _ => return Err(format!("unknown option '{arg}'")),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err("no input capture given".to_owned());
+    }
+    if options.time_range.from_cs >= options.time_range.to_cs {
+        return Err("--from must be earlier than --to".to_owned());
+    }
+    options.format = match format.as_str() {
+        "text" => OutputFormat::Text,
+        "csv" => OutputFormat::Csv { delimiter, header },
+        "jsonl" => OutputFormat::JsonLines,
+        #[cfg(feature = "arrow")]
+        "arrow" => OutputFormat::ArrowFile,
+        #[cfg(feature = "arrow")]
+        "arrow-stream" => OutputFormat::ArrowStream,
+        #[cfg(feature = "parquet")]
+        "parquet" => OutputFormat::Parquet,
+        f => return Err(format!("invalid format '{f}', expected one of {FORMATS}")),
+    };
+    if options.format != OutputFormat::Text && options.messages.is_some() {
+        return Err(format!(
+            "--messages prints text and cannot be used with --format {format}"
+        ));
+    }
+    if reorder && options.messages.is_some() {
+        return Err("--messages prints in packet order and cannot be used with -r".to_owned());
+    }
+    if options.messages.is_some() {
+        let quote_filters = [
+            ("--market", markets),
+            ("--issue", !options.issues.is_empty()),
+            ("--issue-file", !issue_files.is_empty()),
+            (
+                "--range-on accept",
+                options.time_range.field == TimeField::Accept,
+            ),
+        ];
+        if let Some((flag, _)) = quote_filters.iter().find(|(_, given)| *given) {
+            return Err(format!(
+                "--messages prints every message and cannot be used with {flag}"
+            ));
+        }
+    }
+    options.ordering = match (reorder, full_sort) {
+        (false, _) => PacketOrdering::Default,
+        (true, false) => PacketOrdering::StreamingQuoteAcceptTime,
+        (true, true) => PacketOrdering::QuoteAcceptTime,
+    };
+
+    Ok(Command::Run(Box::new(Args {
+        inputs,
+        output,
+        issue_files,
+        options,
+    })))
+}
+
+/// The `-o` path, which must not be empty.
+fn parse_output(flag: &str, path: &str) -> Result<PathBuf, String> {
+    if path.is_empty() {
+        return Err(format!("{flag} requires a value"));
+    }
+    Ok(PathBuf::from(path))
+}
+
+/// Parses `all` or a comma-separated list of one-character market types,
+/// e.g. `4` or `4,5`.
+fn parse_market_types(types: &str) -> Option<MarketTypes> {
+    if types == "all" {
+        return Some(MarketTypes::all());
+    }
+    let types = types
+        .split(',')
+        .map(|t| match t.as_bytes() {
+            &[t] if t.is_ascii_graphic() => Some(t),
+            _ => None,
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    Some(MarketTypes::of(types))
+}
+
+/// Parses a non-negative number of seconds with up to two decimals into
+/// centiseconds, e.g. `3` or `2.5`.
+fn parse_skew_cs(secs: &str) -> Option<u32> {
+    let (whole, frac) = secs.split_once('.').unwrap_or((secs, ""));
+    if whole.is_empty() || frac.len() > 2 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let cs = format!("{frac:0<2}").parse::<u32>().ok()?;
+    whole.parse::<u32>().ok()?.checked_mul(100)?.checked_add(cs)
+}
+
+/// Parses a CSV delimiter: `tab` or one ASCII character that no field can
+/// contain. Fields are never quoted, so `"` is refused too.
+fn parse_delimiter(delimiter: &str) -> Option<u8> {
+    match delimiter.as_bytes() {
+        b"tab" | b"\\t" | b"\t" => Some(b'\t'),
+        &[d] if d.is_ascii_punctuation() && !matches!(d, b'.' | b':' | b'"') => Some(d),
+        b" " => Some(b' '),
+        _ => None,
+    }
+}
+
+/// Parses a time of day, `HH:MM`, `HH:MM:SS` or `HH:MM:SS.ff`, into
+/// centiseconds since midnight.
+fn parse_time_cs(time: &str) -> Option<u32> {
+    let (hms, frac) = time.split_once('.').unwrap_or((time, ""));
+    let mut parts = hms.split(':');
+    let mut part = |max: u32| {
+        let p = parts.next()?;
+        (p.len() == 2 && p.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| p.parse::<u32>().ok())
+            .flatten()
+            .filter(|&n| n <= max)
     };
+    let (hh, mm) = (part(23)?, part(59)?);
+    let ss = match time.matches(':').count() {
+        1 if frac.is_empty() => 0,
+        2 => part(59)?,
+        _ => return None,
+    };
+    if frac.len() > 2 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let cs = format!("{frac:0<2}").parse::<u32>().ok()?;
+    Some(hh * 360_000 + mm * 6_000 + ss * 100 + cs)
+}
 
-    read_pcap_file(
-        PCAP_FILE_PATH,
-        ordering,
-        BufWriter::with_capacity(3 * 1024 * 1024, io::stdout()),
+fn run(mut args: Box<Args>) -> Result<ParseSummary, ParseError> {
+    for path in &args.issue_files {
+        args.options
+            .issues
+            .add_file(path)
+            .map_err(|e| ParseError::File {
+                path: path.clone(),
+                source: Box::new(ParseError::Io(e)),
+            })?;
+    }
+    let out: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(File::create(path).map_err(|e| ParseError::File {
+            path: path.clone(),
+            source: Box::new(ParseError::Write(e)),
+        })?),
+        None => Box::new(io::stdout()),
+    };
+
+    read_pcap_files(
+        &args.inputs,
+        &args.options,
+        BufWriter::with_capacity(OUT_BUF_BYTES, out),
     )
-    .map(|_| ())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Help) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("{NAME} {VERSION}");
+            return ExitCode::SUCCESS;
+        }
+        Err(msg) => {
+            eprintln!("{NAME}: {msg}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let max_skew_cs = args.options.max_skew_cs;
+    let diagnostics = args.options.diagnostics;
+    match run(args) {
+        Ok(summary) => {
+            let rejected = summary.diagnostics.rejected_quotes();
+            if rejected > 0 && !diagnostics {
+                eprintln!(
+                    "{NAME}: warning: skipped {rejected} invalid quotes; rerun with \
+                     --diagnostics to see which fields failed"
+                );
+            }
+            if diagnostics {
+                eprint!("{NAME}: {}", summary.diagnostics);
+            }
+            if summary.skew_violations > 0 {
+                eprintln!(
+                    "{NAME}: warning: {} quotes arrived more than {}.{:02}s from their \
+                     accept time and may be out of order; rerun with --full-sort",
+                    summary.skew_violations,
+                    max_skew_cs / 100,
+                    max_skew_cs % 100
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        // The reader went away (e.g. piped into `head`); nothing left to report.
+        Err(e) if e.io_kind() == Some(io::ErrorKind::BrokenPipe) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{NAME}: {e}");
+            ExitCode::FAILURE
+        }
+    }
 }
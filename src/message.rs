@@ -0,0 +1,197 @@
+//! Message types of the feed and the decoders that print them.
+//!
+//! Only B6034 quotes have a decoder here, as theirs is the one layout in the
+//! spec we work from. Trade (`A3`/`G7`), quote+trade, market status and
+//! issue information messages are printed raw until a decoder for their
+//! layout is registered.
+
+use crate::{
+    feed::{self, END_OF_MESSAGE, HEADER_LEN, QUOTE_HEADER, QUOTE_LEN},
+    write_quote_fields, LineFormat, OutputFormat, OutputMode, Quote, TimePrecision,
+};
+use std::fmt;
+
+/// The 5-byte header every message starts with: data type (2), info type
+/// (2) and market type (1), e.g. `B6034`.
+pub type MessageType = [u8; HEADER_LEN];
+
+/// Decodes one type of message for [`MessageRegistry`].
+///
+/// ```
+/// use kopsi_200_pcap_parser::{MessageDecoder, MessageRegistry};
+///
+/// /// Prints the 12-byte issue code that follows the header.
+/// struct IssueCode;
+///
+/// impl MessageDecoder for IssueCode {
+///     fn name(&self) -> &str {
+///         "trade"
+///     }
+///
+///     fn write_fields(&self, message: &[u8], out: &mut Vec<u8>) -> bool {
+///         let Some(code) = message.get(5..17) else {
+///             return false;
+///         };
+///         out.extend_from_slice(code);
+///         true
+///     }
+/// }
+///
+/// let mut registry = MessageRegistry::kospi200();
+/// registry.register(*b"A3034", IssueCode);
+/// ```
+pub trait MessageDecoder: Send + Sync {
+    /// What the message is, e.g. `quote`.
+    fn name(&self) -> &str;
+
+    /// Length fixed by the spec, end-of-message byte included. `None` (the
+    /// default) for messages that run to the next `0xFF`.
+    fn fixed_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Appends the message's fields to `out` as text, without a newline.
+    /// Returns `false`, having written nothing, for a malformed message.
+    fn write_fields(&self, message: &[u8], out: &mut Vec<u8>) -> bool;
+}
+
+/// B6034 quotes, printed as by `OutputMode::Extended` from the accept time
+/// on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QuoteDecoder;
+
+impl MessageDecoder for QuoteDecoder {
+    fn name(&self) -> &str {
+        "quote"
+    }
+
+    fn fixed_len(&self) -> Option<usize> {
+        Some(QUOTE_LEN)
+    }
+
+    fn write_fields(&self, message: &[u8], out: &mut Vec<u8>) -> bool {
+        let Some(quote) = Quote::new(message) else {
+            return false;
+        };
+        let format = LineFormat {
+            precision: TimePrecision::Millis,
+            mode: OutputMode::Extended,
+            decimals: None,
+            // Already in the message type.
+            market_type: false,
+            style: OutputFormat::Text,
+        };
+        write_quote_fields(out, format, quote);
+        true
+    }
+}
+
+/// Any message whose layout is not known: the text after the header, with
+/// bytes that are not printable ASCII shown as `.`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawDecoder;
+
+impl MessageDecoder for RawDecoder {
+    fn name(&self) -> &str {
+        "raw"
+    }
+
+    fn write_fields(&self, message: &[u8], out: &mut Vec<u8>) -> bool {
+        let body = message.get(HEADER_LEN..).unwrap_or_default();
+        let body = body.strip_suffix(&[END_OF_MESSAGE]).unwrap_or(body);
+        out.extend(body.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b
+            } else {
+                b'.'
+            }
+        }));
+        true
+    }
+}
+
+/// Decoders by message type, used to split payloads into messages and to
+/// print them.
+///
+/// A type with no decoder of its own goes to the fallback decoder, if any;
+/// otherwise it is skipped. Messages are split on the lengths the decoders
+/// fix, and on the `0xFF` end-of-message byte for everything else.
+///
+/// [`new`](Self::new) and `default` start empty; [`kospi200`](Self::kospi200)
+/// is the preset for the KOSPI 200 feed.
+#[derive(Default)]
+pub struct MessageRegistry {
+    decoders: Vec<(MessageType, Box<dyn MessageDecoder>)>,
+    fallback: Option<Box<dyn MessageDecoder>>,
+}
+
+impl MessageRegistry {
+    /// A registry that knows no message type, like `default`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The KOSPI 200 feed preset: `B6034` quotes decoded with [`QuoteDecoder`] and every other type,
+    /// trades and status messages included, printed by [`RawDecoder`].
+    pub fn kospi200() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(*QUOTE_HEADER, QuoteDecoder)
+            .set_fallback(Some(Box::new(RawDecoder)));
+        registry
+    }
+
+    /// Decodes `message_type` with `decoder`, replacing any decoder it had.
+    pub fn register(
+        &mut self,
+        message_type: MessageType,
+        decoder: impl MessageDecoder + 'static,
+    ) -> &mut Self {
+        self.decoders.retain(|(t, _)| *t != message_type);
+        self.decoders.push((message_type, Box::new(decoder)));
+        self
+    }
+
+    /// Sets the decoder for types with none of their own; `None` skips them.
+    pub fn set_fallback(&mut self, decoder: Option<Box<dyn MessageDecoder>>) -> &mut Self {
+        self.fallback = decoder;
+        self
+    }
+
+    /// The decoder registered for the type of `message`, ignoring the
+    /// fallback.
+    fn registered(&self, message: &[u8]) -> Option<&dyn MessageDecoder> {
+        let message_type = message.get(..HEADER_LEN)?;
+        self.decoders
+            .iter()
+            .find(|(t, _)| t == message_type)
+            .map(|(_, decoder)| decoder.as_ref())
+    }
+
+    /// The decoder for the message starting `message`, falling back to the
+    /// fallback decoder.
+    pub fn decoder(&self, message: &[u8]) -> Option<&dyn MessageDecoder> {
+        self.registered(message).or(self.fallback.as_deref())
+    }
+
+    /// `(offset, message)` for each message in a UDP payload, in order.
+    pub fn messages<'a>(&'a self, payload: &'a [u8]) -> impl Iterator<Item = (usize, &'a [u8])> {
+        feed::messages_with(payload, |rest| {
+            self.registered(rest)
+                .and_then(|decoder| decoder.fixed_len())
+        })
+    }
+}
+
+impl fmt::Debug for MessageRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (message_type, decoder) in &self.decoders {
+            map.entry(&String::from_utf8_lossy(message_type), &decoder.name());
+        }
+        if let Some(fallback) = &self.fallback {
+            map.entry(&"*", &fallback.name());
+        }
+        map.finish()
+    }
+}
@@ -0,0 +1,240 @@
+//! Link, network and transport header decoding.
+
+/// UDP destination ports the feed is broadcast on.
+pub(crate) const QUOTE_PORTS: [u16; 2] = [15515, 15516];
+
+// Link types from the tcpdump.org registry that we can decode.
+const LINKTYPE_NULL: u16 = 0;
+pub(crate) const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LOOP: u16 = 108;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+/// Linux cooked capture header lengths and where they keep the ethertype.
+const SLL_HDR_LEN: usize = 16;
+const SLL_PROTOCOL: usize = 14;
+const SLL2_HDR_LEN: usize = 20;
+const SLL2_PROTOCOL: usize = 0;
+
+/// BSD loopback: a 4-byte address family before the IP header.
+const LOOPBACK_HDR_LEN: usize = 4;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+const ETHERTYPE_QINQ_OLD: u16 = 0x9100;
+
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_FRAGMENT: u8 = 44;
+const IPPROTO_AH: u8 = 51;
+const IPPROTO_DSTOPTS: u8 = 60;
+
+const UDP_HDR_LEN: usize = 8;
+
+/// Most VLAN tags we look through before giving up on a frame.
+const MAX_VLAN_TAGS: usize = 4;
+
+/// Most IPv6 extension headers we walk before giving up on a packet.
+const MAX_IPV6_EXT_HEADERS: usize = 8;
+
+/// A UDP payload located inside a captured frame.
+pub(crate) struct Datagram<'a> {
+    pub(crate) dst_port: u16,
+    /// Offset of `payload` from the start of the frame.
+    pub(crate) offset: usize,
+    pub(crate) payload: &'a [u8],
+}
+
+/// Identifies the fragments of one IPv4 datagram (RFC 791).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct FragmentKey {
+    pub(crate) src: [u8; 4],
+    pub(crate) dst: [u8; 4],
+    pub(crate) id: u16,
+    pub(crate) proto: u8,
+}
+
+/// One fragment of a UDP-carrying IPv4 datagram.
+pub(crate) struct Fragment<'a> {
+    pub(crate) key: FragmentKey,
+    /// Byte offset of `data` within the reassembled IP payload.
+    pub(crate) offset: usize,
+    /// More-fragments flag; clear on the last fragment.
+    pub(crate) more: bool,
+    /// Offset of `data` from the start of the frame.
+    pub(crate) frame_offset: usize,
+    pub(crate) data: &'a [u8],
+}
+
+/// What a frame carries once its headers are decoded.
+pub(crate) enum Packet<'a> {
+    Datagram(Datagram<'a>),
+    Fragment(Fragment<'a>),
+}
+
+#[inline]
+fn u16_be(b: &[u8], j: usize) -> u16 {
+    u16::from_be_bytes([b[j], b[j + 1]])
+}
+
+/// Decodes a frame of the given link type down to an unfragmented UDP
+/// datagram.
+pub(crate) fn udp_datagram(linktype: u16, frame: &[u8]) -> Option<Datagram<'_>> {
+    match decode(linktype, frame)? {
+        Packet::Datagram(dgram) => Some(dgram),
+        Packet::Fragment(_) => None,
+    }
+}
+
+/// Decodes a frame of the given link type down to a UDP datagram or a
+/// fragment of an IPv4 datagram carrying UDP.
+///
+/// Header lengths are read from the headers themselves (VLAN tags, IPv4
+/// options, IPv6 extension headers), and the payload is bounded by the IP
+/// and UDP lengths so link-layer padding is never mistaken for payload.
+pub(crate) fn decode(linktype: u16, frame: &[u8]) -> Option<Packet<'_>> {
+    match linktype {
+        LINKTYPE_ETHERNET => ethernet(frame),
+        LINKTYPE_LINUX_SLL => {
+            let ethertype = u16_be(frame.get(..SLL_HDR_LEN)?, SLL_PROTOCOL);
+            ethertype_payload(frame, ethertype, SLL_HDR_LEN)
+        }
+        LINKTYPE_LINUX_SLL2 => {
+            let ethertype = u16_be(frame.get(..SLL2_HDR_LEN)?, SLL2_PROTOCOL);
+            ethertype_payload(frame, ethertype, SLL2_HDR_LEN)
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => ip(frame, 0),
+        // The address family is in the writer's byte order (NULL) or network
+        // order (LOOP), and AF_INET6 differs per OS; the IP version nibble
+        // says the same thing unambiguously.
+        LINKTYPE_NULL | LINKTYPE_LOOP => ip(frame, LOOPBACK_HDR_LEN),
+        _ => None,
+    }
+}
+
+/// Decodes the UDP header at the start of a reassembled IPv4 payload.
+pub(crate) fn reassembled_udp(ip_payload: &[u8]) -> Option<Datagram<'_>> {
+    udp(ip_payload, 0, ip_payload.len())
+}
+
+/// Whether `decode` can decode frames of this link type.
+pub(crate) fn is_supported_linktype(linktype: u16) -> bool {
+    matches!(
+        linktype,
+        LINKTYPE_NULL
+            | LINKTYPE_ETHERNET
+            | LINKTYPE_RAW
+            | LINKTYPE_LOOP
+            | LINKTYPE_LINUX_SLL
+            | LINKTYPE_IPV4
+            | LINKTYPE_IPV6
+            | LINKTYPE_LINUX_SLL2
+    )
+}
+
+/// An IP packet with no link header, dispatched on its version nibble.
+fn ip(frame: &[u8], pos: usize) -> Option<Packet<'_>> {
+    match frame.get(pos)? >> 4 {
+        4 => ipv4(frame, pos),
+        6 => ipv6(frame, pos),
+        _ => None,
+    }
+}
+
+fn ethernet(frame: &[u8]) -> Option<Packet<'_>> {
+    let mut pos = 12;
+    for _ in 0..=MAX_VLAN_TAGS {
+        let ethertype = u16_be(frame.get(..pos + 2)?, pos);
+        match ethertype {
+            // 802.1Q / 802.1ad tag: TCI, then the inner ethertype.
+            ETHERTYPE_VLAN | ETHERTYPE_QINQ | ETHERTYPE_QINQ_OLD => pos += 4,
+            _ => return ethertype_payload(frame, ethertype, pos + 2),
+        }
+    }
+    None
+}
+
+fn ethertype_payload(frame: &[u8], ethertype: u16, pos: usize) -> Option<Packet<'_>> {
+    match ethertype {
+        ETHERTYPE_IPV4 => ipv4(frame, pos),
+        ETHERTYPE_IPV6 => ipv6(frame, pos),
+        _ => None,
+    }
+}
+
+fn ipv4(frame: &[u8], ip: usize) -> Option<Packet<'_>> {
+    let hdr = frame.get(ip..ip + 20)?;
+    let ihl = (hdr[0] & 0x0f) as usize * 4;
+    if hdr[0] >> 4 != 4 || ihl < 20 || hdr[9] != IPPROTO_UDP {
+        return None;
+    }
+    let ip_end = (ip + u16_be(hdr, 2) as usize).min(frame.len());
+    let payload = ip + ihl;
+    if payload > ip_end {
+        return None;
+    }
+    // More-fragments flag or a non-zero offset: part of a larger datagram.
+    let flags_offset = u16_be(hdr, 6);
+    if flags_offset & 0x3fff == 0 {
+        return udp(frame, payload, ip_end).map(Packet::Datagram);
+    }
+    Some(Packet::Fragment(Fragment {
+        key: FragmentKey {
+            src: hdr[12..16].try_into().unwrap(),
+            dst: hdr[16..20].try_into().unwrap(),
+            id: u16_be(hdr, 4),
+            proto: hdr[9],
+        },
+        offset: (flags_offset & 0x1fff) as usize * 8,
+        more: flags_offset & 0x2000 != 0,
+        frame_offset: payload,
+        data: &frame[payload..ip_end],
+    }))
+}
+
+fn ipv6(frame: &[u8], ip: usize) -> Option<Packet<'_>> {
+    let hdr = frame.get(ip..ip + 40)?;
+    if hdr[0] >> 4 != 6 {
+        return None;
+    }
+    let ip_end = (ip + 40 + u16_be(hdr, 4) as usize).min(frame.len());
+    let mut next = hdr[6];
+    let mut pos = ip + 40;
+    for _ in 0..=MAX_IPV6_EXT_HEADERS {
+        let ext = frame.get(pos..pos + 8)?;
+        let len = match next {
+            IPPROTO_UDP => return udp(frame, pos, ip_end).map(Packet::Datagram),
+            IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => (ext[1] as usize + 1) * 8,
+            IPPROTO_AH => (ext[1] as usize + 2) * 4,
+            // Only an atomic fragment (offset 0, no more fragments) is whole.
+            IPPROTO_FRAGMENT if u16_be(ext, 2) & 0xfff9 == 0 => 8,
+            _ => return None,
+        };
+        next = ext[0];
+        pos += len;
+    }
+    None
+}
+
+fn udp(frame: &[u8], udp: usize, end: usize) -> Option<Datagram<'_>> {
+    if udp + UDP_HDR_LEN > end {
+        return None;
+    }
+    let udp_len = u16_be(frame, udp + 4) as usize;
+    if udp_len < UDP_HDR_LEN {
+        return None;
+    }
+    let offset = udp + UDP_HDR_LEN;
+    let payload_end = (udp + udp_len).min(end);
+    Some(Datagram {
+        dst_port: u16_be(frame, udp + 2),
+        offset,
+        payload: &frame[offset..payload_end],
+    })
+}
@@ -0,0 +1,381 @@
+//! pcapng block parsing: section header, interface descriptions and the
+//! per-interface timestamp resolution used by Enhanced Packet Blocks.
+
+use crate::ParseError;
+use memchr::memmem;
+use std::{iter, sync::OnceLock};
+
+/// The same bytes in either byte order.
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const SECTION_HEADER_BYTES: [u8; 4] = SECTION_HEADER.to_le_bytes();
+pub(crate) const INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+pub(crate) const OBSOLETE_PACKET: u32 = 0x0000_0002;
+pub(crate) const SIMPLE_PACKET: u32 = 0x0000_0003;
+pub(crate) const ENHANCED_PACKET: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// Block types defined by the pcapng spec, used to recognise a block
+/// boundary when resynchronising mid-file.
+const KNOWN_BLOCK_TYPES: [u32; 12] = [
+    0x0A0D_0D0A, // section header
+    0x0000_0001, // interface description
+    0x0000_0002, // packet (obsolete)
+    0x0000_0003, // simple packet
+    0x0000_0004, // name resolution
+    0x0000_0005, // interface statistics
+    0x0000_0006, // enhanced packet
+    0x0000_0007, // IRIG timestamp
+    0x0000_0008, // ARINC 429
+    0x0000_000A, // decryption secrets
+    0x0000_0BAD, // custom
+    0x4000_0BAD, // custom, do not copy
+];
+
+#[inline]
+pub(crate) fn is_known_block_type(block_type: u32) -> bool {
+    KNOWN_BLOCK_TYPES.contains(&block_type)
+}
+
+/// Block type + total length + interface id + 2×timestamp + 2×length.
+pub(crate) const EPB_HDR_LEN: usize = 28;
+
+const OPT_END: u16 = 0;
+const IF_TSRESOL: u16 = 9;
+const IF_TSOFFSET: u16 = 14;
+
+/// One Interface Description Block.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Interface {
+    pub(crate) linktype: u16,
+    /// Offset of the block, for error messages.
+    pub(crate) offset: usize,
+    /// Timestamp units per second, from `if_tsresol` (default 10^6).
+    units_per_sec: u64,
+    /// Seconds added to every timestamp, from `if_tsoffset`.
+    offset_secs: i64,
+}
+
+impl Interface {
+    /// Converts an EPB timestamp to `(seconds, nanoseconds)` since the epoch.
+    #[inline]
+    pub(crate) fn timestamp(&self, high: u32, low: u32) -> (u32, u32) {
+        let units = ((high as u64) << 32) | low as u64;
+        let secs = units / self.units_per_sec;
+        let frac = units % self.units_per_sec;
+        let nsec = (frac as u128 * 1_000_000_000 / self.units_per_sec as u128) as u32;
+        ((secs as i64 + self.offset_secs) as u32, nsec)
+    }
+}
+
+/// Bytes of capture searched for section headers at a time.
+const SECTION_SEARCH_BYTES: usize = 1024 * 1024;
+
+/// One section of the capture: where its header starts and the interfaces
+/// it declares before its first packet block. Interface ids restart at 0
+/// in every section.
+pub(crate) struct Section {
+    pub(crate) start: usize,
+    pub(crate) interfaces: Vec<Interface>,
+    /// Unset for a later section in the other byte order from the first,
+    /// or whose leading blocks are malformed.
+    readable: bool,
+}
+
+/// A `SECTION_SEARCH_BYTES` stretch of the capture: the section headers
+/// that start in it and the one in effect where it starts, each found the
+/// first time a lookup needs it.
+#[derive(Default)]
+struct Region {
+    headers: OnceLock<Vec<Section>>,
+    /// `(region, index)` of the last section header before this region, or
+    /// `None` for the first section.
+    carried: OnceLock<Option<(usize, usize)>>,
+}
+
+/// The sections of a pcapng capture, as in files concatenated with `cat`.
+///
+/// Only the first section header is read when the capture is opened.
+/// Later ones are found by searching for the header's block type, checked
+/// against its byte order magic and both copies of its length, a region at
+/// a time, when a lookup first needs that region. So the first window of a
+/// scan, or a time range near the start, never reads far past what it
+/// scans; the section of a packet deep into the capture is only known once
+/// the bytes back to the previous section header have been searched.
+pub(crate) struct Sections {
+    le: bool,
+    data_start: usize,
+    first: Section,
+    regions: Box<[Region]>,
+}
+
+impl Sections {
+    /// Reads the first section header and its interfaces.
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let (le, data_start, interfaces) = parse_section(data, 0)?;
+        let regions = data.len().div_ceil(SECTION_SEARCH_BYTES);
+        Ok(Self {
+            le,
+            data_start,
+            first: Section {
+                start: 0,
+                interfaces,
+                readable: true,
+            },
+            regions: (0..regions).map(|_| Region::default()).collect(),
+        })
+    }
+
+    /// Whether the capture is little-endian, from the first section.
+    pub(crate) fn le(&self) -> bool {
+        self.le
+    }
+
+    /// The offset of the first block after the first section header.
+    pub(crate) fn data_start(&self) -> usize {
+        self.data_start
+    }
+
+    pub(crate) fn first(&self) -> &Section {
+        &self.first
+    }
+
+    /// The section that holds offset `pos`.
+    #[inline]
+    pub(crate) fn at(&self, data: &[u8], pos: usize) -> &Section {
+        let r = (pos / SECTION_SEARCH_BYTES).min(self.regions.len() - 1);
+        let headers = self.headers(data, r);
+        let i = headers.partition_point(|s| s.start <= pos);
+        if i > 0 {
+            return &headers[i - 1];
+        }
+        match self.carried(data, r) {
+            Some((k, i)) => &self.headers(data, k)[i],
+            None => &self.first,
+        }
+    }
+
+    /// Fails with the reason if a section in effect anywhere in `[from,
+    /// to)` cannot be read.
+    pub(crate) fn check(&self, data: &[u8], from: usize, to: usize) -> Result<(), ParseError> {
+        if from >= to {
+            return Ok(());
+        }
+        let later = (from / SECTION_SEARCH_BYTES..=(to - 1) / SECTION_SEARCH_BYTES)
+            .flat_map(|r| self.headers(data, r))
+            .filter(|s| (from..to).contains(&s.start));
+        let Some(section) = iter::once(self.at(data, from))
+            .chain(later)
+            .find(|s| !s.readable)
+        else {
+            return Ok(());
+        };
+        let start = section.start;
+        if byte_order(data, start) != Some(self.le) {
+            return Err(ParseError::SectionByteOrder {
+                offset: start as u64,
+            });
+        }
+        Err(parse_section(data, start).expect_err("unreadable section"))
+    }
+
+    /// The section headers that start in region `r`, read on first use.
+    fn headers(&self, data: &[u8], r: usize) -> &[Section] {
+        self.regions[r].headers.get_or_init(|| {
+            let from = (r * SECTION_SEARCH_BYTES).max(self.data_start);
+            let to = ((r + 1) * SECTION_SEARCH_BYTES).min(data.len());
+            section_headers(data, from, to)
+                .map(|start| {
+                    let interfaces = (byte_order(data, start) == Some(self.le))
+                        .then(|| parse_section(data, start).ok())
+                        .flatten();
+                    Section {
+                        start,
+                        readable: interfaces.is_some(),
+                        interfaces: interfaces.map(|(_, _, i)| i).unwrap_or_default(),
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// The last section header before region `r`, searching back through
+    /// the regions before it until one has a header or already knows.
+    fn carried(&self, data: &[u8], r: usize) -> Option<(usize, usize)> {
+        *self.regions[r].carried.get_or_init(|| {
+            for k in (0..r).rev() {
+                let headers = self.headers(data, k);
+                if !headers.is_empty() {
+                    return Some((k, headers.len() - 1));
+                }
+                if let Some(&carried) = self.regions[k].carried.get() {
+                    return carried;
+                }
+            }
+            None
+        })
+    }
+}
+
+/// The byte order of the section header at `start`, from its magic:
+/// `Some(true)` for little-endian.
+fn byte_order(data: &[u8], start: usize) -> Option<bool> {
+    let magic: [u8; 4] = data.get(start + 8..start + 12)?.try_into().unwrap();
+    if u32::from_le_bytes(magic) == BYTE_ORDER_MAGIC {
+        Some(true)
+    } else if u32::from_be_bytes(magic) == BYTE_ORDER_MAGIC {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Offsets in `[from, to)` where a well-formed Section Header Block, in
+/// either byte order, starts at a block-aligned offset.
+fn section_headers(data: &[u8], from: usize, to: usize) -> impl Iterator<Item = usize> + '_ {
+    let finder = memmem::Finder::new(&SECTION_HEADER_BYTES);
+    let end = (to + SECTION_HEADER_BYTES.len() - 1).min(data.len());
+    let mut pos = from.min(end);
+    iter::from_fn(move || {
+        // Matches may overlap, so each search resumes one byte on.
+        while let Some(at) = finder.find(&data[pos..end]) {
+            let start = pos + at;
+            pos = start + 1;
+            if start.is_multiple_of(4) && is_section_header(data, start) {
+                return Some(start);
+            }
+        }
+        pos = end;
+        None
+    })
+}
+
+/// Whether `start` holds a byte order magic and two agreeing copies of a
+/// plausible block length.
+fn is_section_header(data: &[u8], start: usize) -> bool {
+    let Some(le) = byte_order(data, start) else {
+        return false;
+    };
+    let len: [u8; 4] = data[start + 4..start + 8].try_into().unwrap();
+    let len = if le {
+        u32::from_le_bytes(len)
+    } else {
+        u32::from_be_bytes(len)
+    } as usize;
+    len >= 28
+        && len.is_multiple_of(4)
+        && len <= data.len() - start
+        && data[start + len - 4..start + len] == data[start + 4..start + 8]
+}
+
+/// Reads the section header at `start` and walks the leading blocks up to
+/// the first packet block, collecting every Interface Description Block on
+/// the way. Returns the byte order, the offset after the header and the
+/// interfaces.
+///
+/// Writers emit IDBs before the packets that reference them, normally all
+/// at the start of the section, so this touches only the first few pages
+/// of the section. Packets on interfaces declared later are skipped.
+fn parse_section(data: &[u8], start: usize) -> Result<(bool, usize, Vec<Interface>), ParseError> {
+    let header_error = ParseError::TruncatedHeader {
+        offset: start as u64,
+    };
+    if data.len() - start < 28 {
+        return Err(header_error);
+    }
+    let le = byte_order(data, start).ok_or_else(|| {
+        let found = u32::from_be_bytes(data[start + 8..start + 12].try_into().unwrap());
+        ParseError::BadMagic {
+            offset: start as u64 + 8,
+            found,
+        }
+    })?;
+    let u32_at = |j: usize| -> u32 {
+        let b: [u8; 4] = data[j..j + 4].try_into().unwrap();
+        if le {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        }
+    };
+
+    let shb_len = u32_at(start + 4) as usize;
+    if shb_len < 28 || !shb_len.is_multiple_of(4) || shb_len > data.len() - start {
+        return Err(header_error);
+    }
+
+    let mut interfaces = Vec::new();
+    let mut pos = start + shb_len;
+    while pos + 12 <= data.len() {
+        let block_type = u32_at(pos);
+        let block_len = u32_at(pos + 4) as usize;
+        if block_len < 12 || !block_len.is_multiple_of(4) || pos + block_len > data.len() {
+            return Err(ParseError::TruncatedRecord { offset: pos as u64 });
+        }
+        match block_type {
+            ENHANCED_PACKET | SIMPLE_PACKET | OBSOLETE_PACKET | SECTION_HEADER => break,
+            INTERFACE_DESCRIPTION if block_len >= 20 => {
+                let body = &data[pos + 8..pos + block_len - 4];
+                interfaces.push(parse_interface(body, le, pos));
+            }
+            _ => {}
+        }
+        pos += block_len;
+    }
+
+    Ok((le, start + shb_len, interfaces))
+}
+
+/// Parses an IDB body (everything between the length fields).
+fn parse_interface(body: &[u8], le: bool, offset: usize) -> Interface {
+    let u16_at = |j: usize| -> u16 {
+        let b: [u8; 2] = body[j..j + 2].try_into().unwrap();
+        if le {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        }
+    };
+
+    let mut iface = Interface {
+        linktype: u16_at(0),
+        offset,
+        units_per_sec: 1_000_000,
+        offset_secs: 0,
+    };
+
+    // Options follow linktype(2) + reserved(2) + snaplen(4).
+    let mut pos = 8;
+    while pos + 4 <= body.len() {
+        let code = u16_at(pos);
+        let len = u16_at(pos + 2) as usize;
+        let value = &body[(pos + 4).min(body.len())..(pos + 4 + len).min(body.len())];
+        match code {
+            OPT_END => break,
+            IF_TSRESOL if value.len() == 1 => {
+                let v = value[0];
+                // MSB clear: 10^-v seconds per unit; set: 2^-(v & 0x7f).
+                let units = if v & 0x80 == 0 {
+                    10u64.checked_pow(v as u32)
+                } else {
+                    1u64.checked_shl((v & 0x7f) as u32)
+                };
+                if let Some(units) = units.filter(|&u| u > 0) {
+                    iface.units_per_sec = units;
+                }
+            }
+            IF_TSOFFSET if value.len() == 8 => {
+                let b: [u8; 8] = value.try_into().unwrap();
+                iface.offset_secs = if le {
+                    i64::from_le_bytes(b)
+                } else {
+                    i64::from_be_bytes(b)
+                };
+            }
+            _ => {}
+        }
+        // Option values are padded to 32 bits.
+        pos += 4 + len.next_multiple_of(4);
+    }
+    iface
+}
@@ -0,0 +1,109 @@
+//! Decoding of the feed's fixed-width numeric fields.
+
+use std::fmt;
+
+/// Most implied decimals a price may have.
+pub const MAX_DECIMALS: u8 = 9;
+
+/// Parses a fixed-width field of ASCII digits, such as any of the numeric
+/// [`Quote`](crate::Quote) fields; `None` if a byte is not a digit or the
+/// value does not fit a `u64`.
+#[inline]
+pub fn parse_digits(field: &[u8]) -> Option<u64> {
+    field.iter().try_fold(0u64, |n, &b| {
+        let digit = b.is_ascii_digit().then(|| (b - b'0') as u64)?;
+        n.checked_mul(10)?.checked_add(digit)
+    })
+}
+
+/// A price as an integer number of `10^-decimals` units, e.g. 279.96 is
+/// `Price { units: 27996, decimals: 2 }`. Displays as a decimal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Price {
+    pub units: u64,
+    pub decimals: u8,
+}
+
+impl Price {
+    /// The price as a float, for when exactness does not matter.
+    pub fn to_f64(&self) -> f64 {
+        self.units as f64 / 10f64.powi(self.decimals as i32)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.decimals == 0 {
+            return write!(f, "{}", self.units);
+        }
+        let scale = 10u64.pow(self.decimals as u32);
+        let width = self.decimals as usize;
+        write!(f, "{}.{:0width$}", self.units / scale, self.units % scale)
+    }
+}
+
+/// Implied decimals of each product's prices, by issue code prefix.
+///
+/// The feed sends prices as integers; how many of their digits are
+/// decimals depends on the product. KOSPI 200 futures (`KR41…`) and
+/// options (`KR42…`, `KR43…`) are both quoted in points with two
+/// decimals, which is the default for every issue.
+///
+/// ```
+/// use kopsi_200_pcap_parser::PriceDecimals;
+///
+/// let decimals = PriceDecimals::new(2).with_prefix("KR42", 3);
+/// assert_eq!(decimals.for_issue(b"KR4101F30009"), 2);
+/// assert_eq!(decimals.for_issue(b"KR4201F32606"), 3);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PriceDecimals {
+    default: u8,
+    /// `(issue code prefix, decimals)`; the longest matching prefix wins.
+    prefixes: Vec<(Vec<u8>, u8)>,
+}
+
+impl Default for PriceDecimals {
+    fn default() -> Self {
+        Self::new(2)
+    }
+}
+
+impl PriceDecimals {
+    /// `decimals` for every issue. Panics above [`MAX_DECIMALS`].
+    pub fn new(decimals: u8) -> Self {
+        assert!(decimals <= MAX_DECIMALS, "at most {MAX_DECIMALS} decimals");
+        Self {
+            default: decimals,
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Replaces the decimals of issues no prefix matches. Panics above
+    /// [`MAX_DECIMALS`].
+    pub fn with_default(mut self, decimals: u8) -> Self {
+        assert!(decimals <= MAX_DECIMALS, "at most {MAX_DECIMALS} decimals");
+        self.default = decimals;
+        self
+    }
+
+    /// Uses `decimals` for issue codes starting with `prefix`. Panics above
+    /// [`MAX_DECIMALS`].
+    pub fn with_prefix(mut self, prefix: impl Into<Vec<u8>>, decimals: u8) -> Self {
+        assert!(decimals <= MAX_DECIMALS, "at most {MAX_DECIMALS} decimals");
+        let prefix = prefix.into();
+        self.prefixes.retain(|(p, _)| *p != prefix);
+        self.prefixes.push((prefix, decimals));
+        self
+    }
+
+    /// Implied decimals of the issue's prices.
+    #[inline]
+    pub fn for_issue(&self, issue_code: &[u8]) -> u8 {
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| issue_code.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |&(_, decimals)| decimals)
+    }
+}
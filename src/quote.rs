@@ -0,0 +1,375 @@
+//! Zero-copy view over a B6034 quote message.
+
+use crate::{
+    feed::{self, END_OF_MESSAGE, HEADER_LEN, QUOTE_LEN},
+    price::{parse_digits, Price, MAX_DECIMALS},
+};
+use std::fmt;
+
+/// Price levels per side of the book.
+pub const LEVELS: usize = 5;
+
+// Field offsets within the message.
+const ISSUE_CODE: usize = 5;
+const ISSUE_SEQ_NO: usize = 17;
+const MARKET_STATUS: usize = 20;
+const TOTAL_BID_VOLUME: usize = 22;
+const BIDS: usize = 29;
+const TOTAL_ASK_VOLUME: usize = 89;
+const ASKS: usize = 96;
+const BID_VALID_QUOTES: usize = 156;
+const ASK_VALID_QUOTES: usize = 181;
+const ACCEPT_TIME: usize = 206;
+const ISSUE_SEQ_NO_LEN: usize = 3;
+
+const PRICE_LEN: usize = 5;
+const QUANTITY_LEN: usize = 7;
+const LEVEL_LEN: usize = PRICE_LEN + QUANTITY_LEN;
+const VOLUME_LEN: usize = 7;
+const VALID_QUOTES_TOTAL_LEN: usize = 5;
+const VALID_QUOTES_LEN: usize = 4;
+
+/// One price level of the book, as the ASCII digits sent by the exchange.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Level<'a> {
+    /// 5 digits.
+    pub price: &'a [u8],
+    /// 7 digits.
+    pub quantity: &'a [u8],
+}
+
+impl Level<'_> {
+    /// The price digits as an integer, in units of the product's implied
+    /// decimals; `None` if they are not all digits.
+    #[inline]
+    pub fn price_units(&self) -> Option<u64> {
+        parse_digits(self.price)
+    }
+
+    /// The price with `decimals` implied decimals, usually
+    /// [`PriceDecimals::for_issue`](crate::PriceDecimals::for_issue) of the
+    /// quote's issue code. Panics above `MAX_DECIMALS`.
+    #[inline]
+    pub fn scaled_price(&self, decimals: u8) -> Option<Price> {
+        assert!(decimals <= MAX_DECIMALS, "at most {MAX_DECIMALS} decimals");
+        let units = self.price_units()?;
+        Some(Price { units, decimals })
+    }
+
+    /// The quantity as an integer.
+    #[inline]
+    pub fn quantity_value(&self) -> Option<u64> {
+        parse_digits(self.quantity)
+    }
+}
+
+/// Market types, the last byte of the message header (`4` in `B6034`),
+/// whose quotes are kept.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MarketTypes {
+    /// One bit per byte value.
+    bits: [u64; 4],
+}
+
+impl MarketTypes {
+    /// KOSPI 200 futures, the `B6034` quotes.
+    pub const FUTURES: u8 = b'4';
+
+    /// Every market type.
+    pub fn all() -> Self {
+        Self { bits: [!0; 4] }
+    }
+
+    /// Just `market_type`.
+    pub fn only(market_type: u8) -> Self {
+        Self::of([market_type])
+    }
+
+    /// The given market types.
+    pub fn of(market_types: impl IntoIterator<Item = u8>) -> Self {
+        let mut bits = [0; 4];
+        for t in market_types {
+            bits[t as usize / 64] |= 1 << (t % 64);
+        }
+        Self { bits }
+    }
+
+    #[inline]
+    pub fn contains(&self, market_type: u8) -> bool {
+        self.bits[market_type as usize / 64] & (1 << (market_type % 64)) != 0
+    }
+
+    /// The market type, if exactly one is kept.
+    pub fn single(&self) -> Option<u8> {
+        let mut types = (0..=u8::MAX).filter(|&t| self.contains(t));
+        match (types.next(), types.next()) {
+            (Some(t), None) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MarketTypes {
+    fn default() -> Self {
+        Self::only(Self::FUTURES)
+    }
+}
+
+impl fmt::Debug for MarketTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Self::all() {
+            return f.write_str("MarketTypes(all)");
+        }
+        let types: Vec<char> = (0..=u8::MAX)
+            .filter(|&t| self.contains(t))
+            .map(char::from)
+            .collect();
+        f.debug_tuple("MarketTypes").field(&types).finish()
+    }
+}
+
+/// A B603x quote: the best five bid and ask levels of one issue, of market
+/// type `x` (`4` for KOSPI 200 futures).
+///
+/// Borrows the 215-byte message straight from the capture. Accessors
+/// return the fields as sent, fixed-width ASCII with no decoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quote<'a> {
+    data: &'a [u8; QUOTE_LEN],
+}
+
+impl<'a> Quote<'a> {
+    /// Message length, including the trailing end-of-message byte.
+    pub const LEN: usize = QUOTE_LEN;
+
+    /// Wraps a 215-byte message starting with `B603`, of any market type.
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        feed::is_quote(data).then(|| Self {
+            data: data.try_into().unwrap(),
+        })
+    }
+
+    /// The whole message.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    #[inline]
+    fn field(&self, offset: usize, len: usize) -> &'a [u8] {
+        &self.data[offset..offset + len]
+    }
+
+    /// Market type, the last byte of the header, e.g. `b'4'`.
+    #[inline]
+    pub fn market_type(&self) -> u8 {
+        self.data[HEADER_LEN - 1]
+    }
+
+    /// Issue code, e.g. `KR4101F30009`, without its space padding.
+    #[inline]
+    pub fn issue_code(&self) -> &'a [u8] {
+        let code = self.field(ISSUE_CODE, 12);
+        let len = code.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+        &code[..len]
+    }
+
+    /// Sequence number of the issue within the feed, 3 digits.
+    pub fn issue_seq_no(&self) -> &'a [u8] {
+        self.field(ISSUE_SEQ_NO, ISSUE_SEQ_NO_LEN)
+    }
+
+    /// Market status type, 2 characters.
+    pub fn market_status(&self) -> &'a [u8] {
+        self.field(MARKET_STATUS, 2)
+    }
+
+    /// Total quantity bid, 7 digits.
+    pub fn total_bid_volume(&self) -> &'a [u8] {
+        self.field(TOTAL_BID_VOLUME, VOLUME_LEN)
+    }
+
+    /// Total quantity asked, 7 digits.
+    pub fn total_ask_volume(&self) -> &'a [u8] {
+        self.field(TOTAL_ASK_VOLUME, VOLUME_LEN)
+    }
+
+    /// Bid level `level`, 0 being the best. Panics if `level >= LEVELS`.
+    #[inline]
+    pub fn bid(&self, level: usize) -> Level<'a> {
+        self.level(BIDS, level)
+    }
+
+    /// Ask level `level`, 0 being the best. Panics if `level >= LEVELS`.
+    #[inline]
+    pub fn ask(&self, level: usize) -> Level<'a> {
+        self.level(ASKS, level)
+    }
+
+    /// Bid levels, best first.
+    pub fn bids(&self) -> impl Iterator<Item = Level<'a>> + '_ {
+        (0..LEVELS).map(|i| self.bid(i))
+    }
+
+    /// Ask levels, best first.
+    pub fn asks(&self) -> impl Iterator<Item = Level<'a>> + '_ {
+        (0..LEVELS).map(|i| self.ask(i))
+    }
+
+    #[inline]
+    fn level(&self, side: usize, level: usize) -> Level<'a> {
+        assert!(level < LEVELS, "price level {level} out of range");
+        let at = side + level * LEVEL_LEN;
+        Level {
+            price: self.field(at, PRICE_LEN),
+            quantity: self.field(at + PRICE_LEN, QUANTITY_LEN),
+        }
+    }
+
+    /// Number of valid bid quotes over all levels, 5 digits.
+    pub fn bid_valid_quotes_total(&self) -> &'a [u8] {
+        self.field(BID_VALID_QUOTES, VALID_QUOTES_TOTAL_LEN)
+    }
+
+    /// Number of valid ask quotes over all levels, 5 digits.
+    pub fn ask_valid_quotes_total(&self) -> &'a [u8] {
+        self.field(ASK_VALID_QUOTES, VALID_QUOTES_TOTAL_LEN)
+    }
+
+    /// Valid bid quotes at `level`, 4 digits. Panics if `level >= LEVELS`.
+    pub fn bid_valid_quotes(&self, level: usize) -> &'a [u8] {
+        self.valid_quotes(BID_VALID_QUOTES, level)
+    }
+
+    /// Valid ask quotes at `level`, 4 digits. Panics if `level >= LEVELS`.
+    pub fn ask_valid_quotes(&self, level: usize) -> &'a [u8] {
+        self.valid_quotes(ASK_VALID_QUOTES, level)
+    }
+
+    fn valid_quotes(&self, side: usize, level: usize) -> &'a [u8] {
+        assert!(level < LEVELS, "price level {level} out of range");
+        let at = side + VALID_QUOTES_TOTAL_LEN + level * VALID_QUOTES_LEN;
+        self.field(at, VALID_QUOTES_LEN)
+    }
+
+    /// Time the exchange accepted the quote, `HHMMSSuu` in KST.
+    #[inline]
+    pub fn accept_time(&self) -> &'a [u8] {
+        self.field(ACCEPT_TIME, 8)
+    }
+
+    /// Accept time as centiseconds since midnight KST. Meaningless, but
+    /// never a panic, unless the quote passes [`validate`](Self::validate).
+    #[inline]
+    pub fn accept_time_cs(&self) -> u32 {
+        let t = self.accept_time();
+        let (hh, mm, ss, cc) = (aa(&t[0..2]), aa(&t[2..4]), aa(&t[4..6]), aa(&t[6..8]));
+        hh * 360_000 + mm * 6_000 + ss * 100 + cc
+    }
+
+    /// Checks that every numeric field is ASCII digits, that the accept time
+    /// is a time of day and that the message ends with the end-of-message
+    /// byte. Returns the first field, in message order, that does not.
+    pub fn validate(&self) -> Result<(), QuoteField> {
+        let digits = |field: &[u8]| field.iter().all(u8::is_ascii_digit);
+        let check = |ok: bool, field: QuoteField| if ok { Ok(()) } else { Err(field) };
+
+        check(digits(self.issue_seq_no()), QuoteField::IssueSeqNo)?;
+        check(digits(self.total_bid_volume()), QuoteField::TotalBidVolume)?;
+        for (i, level) in self.bids().enumerate() {
+            check(digits(level.price), QuoteField::BidPrice(i))?;
+            check(digits(level.quantity), QuoteField::BidQuantity(i))?;
+        }
+        check(digits(self.total_ask_volume()), QuoteField::TotalAskVolume)?;
+        for (i, level) in self.asks().enumerate() {
+            check(digits(level.price), QuoteField::AskPrice(i))?;
+            check(digits(level.quantity), QuoteField::AskQuantity(i))?;
+        }
+        let bid_counts = self.field(
+            BID_VALID_QUOTES,
+            VALID_QUOTES_TOTAL_LEN + LEVELS * VALID_QUOTES_LEN,
+        );
+        check(digits(bid_counts), QuoteField::BidValidQuotes)?;
+        let ask_counts = self.field(
+            ASK_VALID_QUOTES,
+            VALID_QUOTES_TOTAL_LEN + LEVELS * VALID_QUOTES_LEN,
+        );
+        check(digits(ask_counts), QuoteField::AskValidQuotes)?;
+
+        let t = self.accept_time();
+        let time_of_day = digits(t) && aa(&t[0..2]) < 24 && aa(&t[2..4]) < 60 && aa(&t[4..6]) < 60;
+        check(time_of_day, QuoteField::AcceptTime)?;
+        check(
+            self.data[QUOTE_LEN - 1] == END_OF_MESSAGE,
+            QuoteField::EndOfMessage,
+        )
+    }
+}
+
+/// Manually parse exactly two ASCII digits, used for HH, MM, SS, etc.
+/// Other bytes give a wrong value rather than a panic.
+#[inline]
+fn aa(b: &[u8]) -> u32 {
+    (b[0].wrapping_sub(b'0')) as u32 * 10 + (b[1].wrapping_sub(b'0')) as u32
+}
+
+/// A quote field that failed [`Quote::validate`]. Levels count from 0, the
+/// best, as in [`Quote::bid`]; `Display` numbers them from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum QuoteField {
+    IssueSeqNo,
+    TotalBidVolume,
+    BidPrice(usize),
+    BidQuantity(usize),
+    TotalAskVolume,
+    AskPrice(usize),
+    AskQuantity(usize),
+    /// The total or one of the per-level counts.
+    BidValidQuotes,
+    AskValidQuotes,
+    AcceptTime,
+    /// The trailing `0xFF`.
+    EndOfMessage,
+}
+
+impl QuoteField {
+    /// The field's name, without the level.
+    pub fn name(&self) -> &'static str {
+        &self.kind()["invalid ".len()..]
+    }
+
+    /// `invalid <name>`, grouping the skip report by field.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            QuoteField::IssueSeqNo => "invalid issue seq no",
+            QuoteField::TotalBidVolume => "invalid total bid volume",
+            QuoteField::BidPrice(_) => "invalid bid price",
+            QuoteField::BidQuantity(_) => "invalid bid quantity",
+            QuoteField::TotalAskVolume => "invalid total ask volume",
+            QuoteField::AskPrice(_) => "invalid ask price",
+            QuoteField::AskQuantity(_) => "invalid ask quantity",
+            QuoteField::BidValidQuotes => "invalid bid valid quotes",
+            QuoteField::AskValidQuotes => "invalid ask valid quotes",
+            QuoteField::AcceptTime => "invalid accept time",
+            QuoteField::EndOfMessage => "invalid end-of-message byte",
+        }
+    }
+}
+
+impl fmt::Display for QuoteField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuoteField::BidPrice(level)
+            | QuoteField::BidQuantity(level)
+            | QuoteField::AskPrice(level)
+            | QuoteField::AskQuantity(level) => write!(f, "level {} {}", level + 1, self.name()),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+/// Every quote, of any market type, in a UDP payload from the feed, in
+/// order. The payload may batch several messages of any type back to back.
+pub fn payload_quotes(payload: &[u8]) -> impl Iterator<Item = Quote<'_>> {
+    feed::quotes(payload).filter_map(|(_, m)| Quote::new(m))
+}
@@ -0,0 +1,215 @@
+//! Pull-style access to the quotes in a capture, one at a time.
+
+#[cfg(feature = "arrow")]
+use crate::QuoteBatches;
+use crate::{
+    capture::Capture, feed::QUOTE_LEN, map_file, scan_quotes, scan_range, Diagnostics, ParseError,
+    ParseOptions, Quote, WINDOW_BYTES,
+};
+use memmap2::Mmap;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    vec,
+};
+
+/// A quote together with where and when it was captured.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuoteRecord {
+    /// Packet time, seconds since the Unix epoch (UTC).
+    pub ts_sec: u32,
+    /// Nanoseconds within `ts_sec`, at the capture's own resolution.
+    pub ts_nsec: u32,
+    /// Byte offset of the quote in the capture file. Offsets grow in
+    /// arrival order; a quote reassembled from IP fragments is placed
+    /// after the data of the fragment that completed it.
+    pub offset: u64,
+    data: [u8; QUOTE_LEN],
+}
+
+impl QuoteRecord {
+    fn new(ts_sec: u32, ts_nsec: u32, offset: usize, quote: Quote) -> Self {
+        Self {
+            ts_sec,
+            ts_nsec,
+            offset: offset as u64,
+            data: quote.as_bytes().try_into().unwrap(),
+        }
+    }
+
+    /// The decoded quote.
+    pub fn quote(&self) -> Quote<'_> {
+        Quote::new(&self.data).expect("record holds a validated quote")
+    }
+}
+
+/// Iterates over the quotes of one capture in file (packet) order.
+///
+/// The capture is memory-mapped and scanned on the calling thread one
+/// window at a time, so only one window's quotes are buffered. Quotes are
+/// found exactly as by [`read_pcap_files`](crate::read_pcap_files) with the
+/// same [`ScanMode`](crate::ScanMode) and defragmentation limits.
+///
+/// ```no_run
+/// use kopsi_200_pcap_parser::QuoteReader;
+///
+/// for record in QuoteReader::open("capture.pcap")? {
+///     let record = record?;
+///     println!("{} {:?}", record.offset, record.quote().issue_code());
+/// }
+/// # Ok::<(), kopsi_200_pcap_parser::ParseError>(())
+/// ```
+pub struct QuoteReader {
+    path: PathBuf,
+    mmap: Mmap,
+    capture: Capture,
+    options: ParseOptions,
+    diagnostics: Diagnostics,
+    /// Start of the next window to scan.
+    next_window: usize,
+    /// End of the part of the capture `options.time_range` can match.
+    end: usize,
+    pending: VecDeque<QuoteRecord>,
+    /// A strict validation failure, yielded after the quotes before it.
+    failed: Option<ParseError>,
+}
+
+impl QuoteReader {
+    /// Opens a capture with the default options.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::with_options(path, &ParseOptions::default())
+    }
+
+    /// Opens a capture; only `scan`, `defrag`, `diagnostics`,
+    /// `validation`, the `market_types`, `issues` and `time_range` filters
+    /// and, for [`record_batches`](Self::record_batches), `price_decimals`
+    /// of `options` apply. Errors are prefixed with the path.
+    pub fn with_options(
+        path: impl AsRef<Path>,
+        options: &ParseOptions,
+    ) -> Result<Self, ParseError> {
+        let path = path.as_ref();
+        let open = || {
+            let mmap = map_file(path, false)?;
+            let capture = Capture::parse(&mmap)?;
+            Ok((mmap, capture))
+        };
+        let (mmap, capture) = open().map_err(|e: ParseError| e.in_file(path))?;
+        let (start, end) = scan_range(&mmap, &capture, options);
+        Ok(Self {
+            path: path.to_owned(),
+            next_window: start,
+            end,
+            mmap,
+            capture,
+            options: options.clone(),
+            diagnostics: Diagnostics::new(options.diagnostics),
+            pending: VecDeque::new(),
+            failed: None,
+        })
+    }
+
+    /// Records skipped so far, if `diagnostics` was set.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Yields the quotes as Arrow record batches of up to `batch_rows` rows
+    /// each, in the columns of [`quote_schema`](crate::quote_schema). The
+    /// `price_decimals` column comes from the reader's options.
+    #[cfg(feature = "arrow")]
+    pub fn record_batches(self, batch_rows: usize) -> QuoteBatches {
+        let decimals = self.options.price_decimals.clone();
+        QuoteBatches::new(self, decimals, batch_rows)
+    }
+
+    /// Reads the whole capture and yields its quotes in accept-time order,
+    /// ties broken by file order, like `PacketOrdering::QuoteAcceptTime`.
+    ///
+    /// Every quote is held in memory until the last one is read. If the
+    /// capture ends in an error, the quotes before it are yielded sorted,
+    /// then the error.
+    pub fn sorted(self) -> SortedQuoteReader {
+        SortedQuoteReader {
+            source: Some(self),
+            records: Vec::new().into_iter(),
+            failed: None,
+        }
+    }
+}
+
+impl Iterator for QuoteReader {
+    type Item = Result<QuoteRecord, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Self {
+            path,
+            mmap,
+            capture,
+            options,
+            diagnostics,
+            next_window,
+            end,
+            pending,
+            failed,
+        } = self;
+        while pending.is_empty() && *next_window < *end {
+            let base = *next_window;
+            let own_end = (base + WINDOW_BYTES).min(*end);
+            let scanned = scan_quotes(
+                mmap,
+                capture,
+                options,
+                base,
+                own_end,
+                diagnostics,
+                |ts_sec, ts_nsec, gpos, quote| {
+                    pending.push_back(QuoteRecord::new(ts_sec, ts_nsec, gpos, quote));
+                },
+            );
+            *next_window = own_end;
+            if let Err(e) = scanned {
+                *failed = Some(e.in_file(&*path));
+                *next_window = *end;
+            }
+        }
+        pending
+            .pop_front()
+            .map(Ok)
+            .or_else(|| failed.take().map(Err))
+    }
+}
+
+/// Quotes of one capture in accept-time order; see [`QuoteReader::sorted`].
+pub struct SortedQuoteReader {
+    /// The unread capture, until the first call to `next`.
+    source: Option<QuoteReader>,
+    records: vec::IntoIter<QuoteRecord>,
+    /// The error that ended the capture, after `records`.
+    failed: Option<ParseError>,
+}
+
+impl Iterator for SortedQuoteReader {
+    type Item = Result<QuoteRecord, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(source) = self.source.take() {
+            let mut records = Vec::new();
+            for record in source {
+                match record {
+                    Ok(record) => records.push(record),
+                    Err(e) => {
+                        self.failed = Some(e);
+                        break;
+                    }
+                }
+            }
+            records.sort_unstable_by_key(|r| (r.quote().accept_time_cs(), r.offset));
+            self.records = records.into_iter();
+        }
+        self.records
+            .next()
+            .map(Ok)
+            .or_else(|| self.failed.take().map(Err))
+    }
+}
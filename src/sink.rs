@@ -0,0 +1,132 @@
+//! Custom per-quote processing on the parallel scan.
+
+use crate::{
+    capture::Capture, map_file, scan_chunks, scan_quotes, scan_range, worker_count, Diagnostics,
+    ParseError, ParseOptions, Quote,
+};
+use std::path::Path;
+
+/// Receives every quote of a capture from several worker threads.
+///
+/// Each worker scans one contiguous part of the file into its own
+/// [`State`](QuoteSink::State), then the states are merged in file order,
+/// so an aggregation sees quotes exactly once and can tell which came
+/// first without any locking.
+///
+/// ```
+/// use kopsi_200_pcap_parser::{Quote, QuoteSink};
+/// use std::collections::HashMap;
+///
+/// /// Quotes per issue code.
+/// struct CountByIssue;
+///
+/// impl QuoteSink for CountByIssue {
+///     type State = HashMap<Vec<u8>, u64>;
+///
+///     fn new_state(&self) -> Self::State {
+///         HashMap::new()
+///     }
+///
+///     fn visit(&self, counts: &mut Self::State, _: u32, _: u32, _: u64, quote: Quote<'_>) {
+///         *counts.entry(quote.issue_code().to_vec()).or_default() += 1;
+///     }
+///
+///     fn merge(&self, counts: &mut Self::State, later: Self::State) {
+///         for (code, n) in later {
+///             *counts.entry(code).or_default() += n;
+///         }
+///     }
+/// }
+/// ```
+pub trait QuoteSink: Sync {
+    /// What one worker accumulates.
+    type State: Send;
+
+    /// A fresh state for a worker (or an empty capture).
+    fn new_state(&self) -> Self::State;
+
+    /// Called for each quote, in file order within a worker. `ts_sec` and
+    /// `ts_nsec` are the packet time (UTC) and `offset` is the quote's byte
+    /// offset in the file, as in [`QuoteRecord`](crate::QuoteRecord).
+    fn visit(
+        &self,
+        state: &mut Self::State,
+        ts_sec: u32,
+        ts_nsec: u32,
+        offset: u64,
+        quote: Quote<'_>,
+    );
+
+    /// Folds `later`, built from the part of the input that follows
+    /// `state`'s, into `state`.
+    fn merge(&self, state: &mut Self::State, later: Self::State);
+}
+
+/// Runs `sink` over every quote of a capture on `options.threads` workers
+/// and returns the merged state. `scan`, `defrag`, `validation` and the
+/// `market_types`, `issues` and `time_range` filters apply as for
+/// [`read_pcap_files`](crate::read_pcap_files); the ordering, output and
+/// diagnostics options do not.
+pub fn process_pcap_file<S: QuoteSink>(
+    path: impl AsRef<Path>,
+    options: &ParseOptions,
+    sink: &S,
+) -> Result<S::State, ParseError> {
+    process_pcap_files(&[path], options, sink)
+}
+
+/// Like [`process_pcap_file`] for several captures, merged in the order
+/// given. Errors opening or parsing a file are prefixed with its path.
+pub fn process_pcap_files<P, S>(
+    paths: &[P],
+    options: &ParseOptions,
+    sink: &S,
+) -> Result<S::State, ParseError>
+where
+    P: AsRef<Path>,
+    S: QuoteSink,
+{
+    let mut state = sink.new_state();
+    for path in paths {
+        let path = path.as_ref();
+        let file_state = process_file(path, options, sink).map_err(|e| e.in_file(path))?;
+        sink.merge(&mut state, file_state);
+    }
+    Ok(state)
+}
+
+fn process_file<S: QuoteSink>(
+    path: &Path,
+    options: &ParseOptions,
+    sink: &S,
+) -> Result<S::State, ParseError> {
+    let mmap = map_file(path, true)?;
+    let capture = Capture::parse(&mmap)?;
+
+    let (start, end) = scan_range(&mmap, &capture, options);
+    let states = scan_chunks(worker_count(options), start, end, |base, own_end| {
+        let mut state = sink.new_state();
+        scan_quotes(
+            &mmap,
+            &capture,
+            options,
+            base,
+            own_end,
+            &mut Diagnostics::new(false),
+            |ts_sec, ts_nsec, gpos, quote| {
+                sink.visit(&mut state, ts_sec, ts_nsec, gpos as u64, quote);
+            },
+        )
+        .map(|()| state)
+    });
+
+    let mut states = states
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter();
+    let mut merged = states.next().unwrap_or_else(|| sink.new_state());
+    for later in states {
+        sink.merge(&mut merged, later);
+    }
+    Ok(merged)
+}
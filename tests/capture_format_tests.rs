@@ -0,0 +1,269 @@
+mod common;
+
+use kopsi_200_pcap_parser::{ParseError, QuoteReader};
+
+#[test]
+fn test_pcapng_matches_classic_pcap_output() {
+    let mut pcap = common::Pcap::new(1);
+    let mut ng = common::PcapNg::new();
+    // Interface 0 uses the default microsecond resolution, 1 nanoseconds.
+    ng.interface(1, None).interface(1, Some(9));
+    for (i, (offset_us, frame)) in common::quote_frames(40, 123_457, 90).enumerate() {
+        pcap.packet(offset_us, &frame);
+        ng.packet(i as u32 % 2, offset_us, &frame);
+    }
+    let pcap = pcap.write("formats_reference.pcap");
+    let ng = ng.write("formats_two_interfaces.pcapng");
+
+    let expected = common::run(&pcap, &[]);
+    assert_eq!(String::from_utf8_lossy(&expected).lines().count(), 40);
+    assert_eq!(common::run(&ng, &[]), expected);
+    assert_eq!(common::run(&ng, &["-r"]), common::run(&pcap, &["-r"]));
+}
+
+#[test]
+fn test_pcapng_binary_timestamp_resolution() {
+    let mut pcap = common::Pcap::new(1);
+    let mut ng = common::PcapNg::new();
+    // 2^-20 s units, which do not divide evenly into microseconds.
+    ng.interface(1, Some(0x80 | 20));
+    for (offset_us, frame) in common::quote_frames(40, 123_457, 90) {
+        pcap.packet(offset_us, &frame);
+        ng.packet(0, offset_us, &frame);
+    }
+    let pcap = pcap.write("formats_reference_binres.pcap");
+    let ng = ng.write("formats_binres.pcapng");
+
+    assert_eq!(common::run(&ng, &[]), common::run(&pcap, &[]));
+}
+
+#[test]
+fn test_concatenated_pcapng_sections_keep_their_interfaces() {
+    // Each section declares its own interface 0, at a different resolution.
+    let mut first = common::PcapNg::new();
+    let mut second = common::PcapNg::new();
+    first.interface(1, None);
+    second.interface(1, Some(9));
+    let mut pcap = common::Pcap::new(1);
+    for (i, (offset_us, frame)) in common::quote_frames(40, 123_457, 90).enumerate() {
+        pcap.packet(offset_us, &frame);
+        let section = if i < 20 { &mut first } else { &mut second };
+        section.packet(0, offset_us, &frame);
+    }
+    let pcap = pcap.write("formats_reference_sections.pcap");
+    let ng = common::write_temp(
+        "formats_sections.pcapng",
+        &[first.bytes(), second.bytes()].concat(),
+    );
+
+    let expected = common::run(&pcap, &["--precision", "us"]);
+    assert_eq!(common::run(&ng, &["--precision", "us"]), expected);
+    assert_eq!(
+        common::run(&ng, &["--precision", "us", "--structural"]),
+        expected
+    );
+    // A range that starts in the second section seeks straight into it.
+    let args = ["--precision", "us", "--from", "09:00:03"];
+    assert_eq!(common::run(&ng, &args), common::run(&pcap, &args));
+
+    // A big-endian section after a little-endian one is refused.
+    let mixed = common::write_temp(
+        "formats_mixed_byte_order.pcapng",
+        &[first.bytes(), &big_endian_section(), second.bytes()].concat(),
+    );
+    assert_refused_byte_order(&mixed);
+}
+
+/// An empty big-endian pcapng section.
+fn big_endian_section() -> Vec<u8> {
+    let mut section = vec![0x0A, 0x0D, 0x0D, 0x0A, 0, 0, 0, 28, 0x1A, 0x2B, 0x3C, 0x4D];
+    section.extend_from_slice(&[0, 1, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    section.extend_from_slice(&[0, 0, 0, 28]);
+    section
+}
+
+fn assert_refused_byte_order(path: &std::path::Path) {
+    let output = common::parser_command(&[path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("other byte order"), "{stderr}");
+}
+
+#[test]
+fn test_pcapng_time_range_reads_no_later_section() {
+    // A first section several windows long, then one the parser refuses,
+    // which only a scan that gets that far may notice.
+    let mut first = common::PcapNg::new();
+    first.interface(1, None);
+    let mut pcap = common::Pcap::new(1);
+    for (offset_us, frame) in common::quote_frames(16_000, 1_500, 250) {
+        pcap.packet(offset_us, &frame);
+        first.packet(0, offset_us, &frame);
+    }
+    let pcap = pcap.write("formats_reference_range.pcap");
+    let ng = common::write_temp(
+        "formats_range_sections.pcapng",
+        &[first.bytes(), &big_endian_section()].concat(),
+    );
+
+    for scan in [&[][..], &["--structural"]] {
+        let args = [scan, &["--from", "09:00:03", "--to", "09:00:06"]].concat();
+        let expected = common::run(&pcap, &args);
+        assert_eq!(String::from_utf8_lossy(&expected).lines().count(), 2_000);
+        assert_eq!(common::run(&ng, &args), expected);
+    }
+    assert_refused_byte_order(&ng);
+}
+
+fn first_packet_time(path: &std::path::Path, args: &[&str]) -> String {
+    let out = String::from_utf8(common::run(path, args)).expect("output should be UTF-8");
+    out.split_ascii_whitespace()
+        .next()
+        .expect("should print a quote")
+        .to_owned()
+}
+
+#[test]
+fn test_nanosecond_pcap_timestamps() {
+    let payload = common::quote_payload("KR4101F30009", 25_000, "09000500");
+    let frame = common::udp_frame(15515, &payload);
+
+    let mut nanos = common::Pcap::with_magic(0xa1b2_3c4d, 1);
+    nanos.raw_packet(common::SESSION_START_UTC + 5, 123_456_789, &frame);
+    let nanos = nanos.write("formats_nanos.pcap");
+
+    assert_eq!(first_packet_time(&nanos, &[]), "09:00:05.123");
+    assert_eq!(
+        first_packet_time(&nanos, &["--precision", "us"]),
+        "09:00:05.123456"
+    );
+    assert_eq!(
+        first_packet_time(&nanos, &["--precision", "ns"]),
+        "09:00:05.123456789"
+    );
+
+    let mut micros = common::Pcap::new(1);
+    micros.raw_packet(common::SESSION_START_UTC + 5, 123_456, &frame);
+    let micros = micros.write("formats_micros.pcap");
+
+    assert_eq!(
+        first_packet_time(&micros, &["--precision", "ns"]),
+        "09:00:05.123456000"
+    );
+}
+
+#[test]
+fn test_cooked_raw_and_loopback_link_types() {
+    let payload = common::quote_payload("KR4101F30009", 25_000, "09000000");
+    let v4 = common::ipv4_packet(0, &common::udp_segment(15515, &payload));
+    let v6 = common::ipv6_packet(false, &common::udp_segment(15516, &payload));
+
+    let sll = |ethertype: u16, l3: &[u8]| {
+        let mut f = vec![0, 0, 0, 1, 0, 6, 0, 0x11, 0x22, 0x33, 0x44, 0x55, 0, 0];
+        f.extend_from_slice(&ethertype.to_be_bytes());
+        [&f[..], l3].concat()
+    };
+    let sll2 = |ethertype: u16, l3: &[u8]| {
+        let mut f = ethertype.to_be_bytes().to_vec();
+        f.extend_from_slice(&[
+            0, 0, 0, 0, 0, 2, 0, 1, 0, 6, 0, 0x11, 0x22, 0x33, 0x44, 0x55, 0, 0,
+        ]);
+        [&f[..], l3].concat()
+    };
+    let null = |family: u32, l3: &[u8]| [&family.to_le_bytes()[..], l3].concat();
+
+    let cases: [(u32, Vec<Vec<u8>>); 4] = [
+        (113, vec![sll(0x0800, &v4), sll(0x86dd, &v6)]),
+        (276, vec![sll2(0x0800, &v4), sll2(0x86dd, &v6)]),
+        (101, vec![v4.clone(), v6.clone()]),
+        (0, vec![null(2, &v4), null(30, &v6)]),
+    ];
+    for (linktype, frames) in cases {
+        let mut pcap = common::Pcap::new(linktype);
+        for (i, frame) in frames.iter().enumerate() {
+            pcap.packet(i as u64 * 1_000, frame);
+        }
+        let path = pcap.write(&format!("formats_linktype_{linktype}.pcap"));
+        for args in [&[][..], &["--structural"][..]] {
+            let out = common::run(&path, args);
+            assert_eq!(
+                String::from_utf8_lossy(&out).lines().count(),
+                2,
+                "link type {linktype} {args:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_unsupported_link_type_is_an_error() {
+    let payload = common::quote_payload("KR4101F30009", 25_000, "09000000");
+    let mut pcap = common::Pcap::new(105); // IEEE 802.11
+    pcap.packet(0, &common::udp_frame(15515, &payload));
+    let path = pcap.write("formats_linktype_105.pcap");
+
+    let output = common::parser_command(&[path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unsupported link type 105"));
+}
+
+#[test]
+fn test_pcapng_packets_on_unsupported_interfaces_are_skipped() {
+    // A Linux USB interface next to an Ethernet one.
+    let mut ng = common::PcapNg::new();
+    ng.interface(189, None).interface(1, None);
+    let mut pcap = common::Pcap::new(1);
+    for (i, (offset_us, frame)) in common::quote_frames(40, 123_457, 90).enumerate() {
+        if i % 4 == 0 {
+            ng.packet(0, offset_us, &frame);
+        } else {
+            pcap.packet(offset_us, &frame);
+            ng.packet(1, offset_us, &frame);
+        }
+    }
+    let pcap = pcap.write("formats_reference_usb.pcap");
+    let ng = ng.write("formats_usb_and_ethernet.pcapng");
+
+    let expected = common::run(&pcap, &[]);
+    assert_eq!(String::from_utf8_lossy(&expected).lines().count(), 30);
+    assert_eq!(common::run(&ng, &[]), expected);
+    let output = common::parser_command(&["--structural", "--diagnostics", ng.to_str().unwrap()]);
+    assert_eq!(output.stdout, expected);
+    let report = String::from_utf8_lossy(&output.stderr);
+    assert!(report.contains("10 records skipped"), "{report}");
+    assert!(report.contains("unsupported link type"), "{report}");
+
+    // With no supported interface at all, nothing could be printed.
+    let mut usb = common::PcapNg::new();
+    usb.interface(189, None);
+    usb.packet(0, 0, &common::quote_frames(1, 0, 1).next().unwrap().1);
+    let usb = usb.write("formats_usb_only.pcapng");
+    let output = common::parser_command(&[usb.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unsupported link type 189"));
+}
+
+#[test]
+fn test_library_errors_name_the_file_and_cause() {
+    let mut pcap = common::Pcap::new(105);
+    pcap.packet(0, &common::udp_frame(15515, b"B6034"));
+    let path = pcap.write("formats_linktype_105_lib.pcap");
+
+    let err = QuoteReader::open(&path).err().expect("should fail");
+    let ParseError::File { path: at, source } = &err else {
+        panic!("expected a file error, got {err:?}");
+    };
+    assert_eq!(at, &path);
+    assert!(matches!(
+        **source,
+        ParseError::UnsupportedLinkType {
+            offset: 20,
+            linktype: 105
+        }
+    ));
+
+    let err = QuoteReader::open(common::write_temp("formats_not_pcap.bin", b"hello, world"))
+        .err()
+        .expect("should fail");
+    assert_eq!(err.kind(), "bad magic number");
+}
@@ -0,0 +1,401 @@
+mod common;
+
+use kopsi_200_pcap_parser::PCAP_FILE_PATH;
+
+#[test]
+fn test_cli_without_inputs_is_a_usage_error() {
+    let output = common::parser_command(&[]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage:"));
+}
+
+#[test]
+fn test_cli_unknown_option_is_a_usage_error() {
+    let output = common::parser_command(&["--bogus", PCAP_FILE_PATH]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'--bogus'"));
+}
+
+#[test]
+fn test_cli_missing_input_reports_path_and_fails() {
+    let output = common::parser_command(&["nonexistent.pcap"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nonexistent.pcap"));
+}
+
+#[test]
+fn test_cli_output_file_matches_stdout() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("cli_output.txt");
+    let path_str = path.to_str().expect("temp path should be UTF-8");
+
+    let stdout = common::parser_output(&[PCAP_FILE_PATH]);
+    common::parser_output(&["-o", path_str, "--threads", "3", PCAP_FILE_PATH]);
+
+    assert_eq!(std::fs::read(&path).expect("output file"), stdout);
+
+    // The path may also be attached to the flag, with or without `=`.
+    for attached in [format!("-o{path_str}"), format!("-o={path_str}")] {
+        std::fs::remove_file(&path).unwrap();
+        common::parser_output(&[&attached, PCAP_FILE_PATH]);
+        assert_eq!(std::fs::read(&path).expect("output file"), stdout);
+    }
+
+    for empty in [&["-o="][..], &["--output="], &["-o", ""]] {
+        let output = common::parser_command(&[empty, &[PCAP_FILE_PATH]].concat());
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("requires a value"));
+    }
+}
+
+#[test]
+fn test_cli_multiple_inputs_are_concatenated_in_order() {
+    let once = common::parser_output(&[PCAP_FILE_PATH]);
+    let twice = common::parser_output(&[PCAP_FILE_PATH, PCAP_FILE_PATH]);
+
+    assert_eq!(twice, [once.as_slice(), once.as_slice()].concat());
+}
+
+#[test]
+fn test_cli_streaming_reorder_matches_full_sort() {
+    let streamed = common::parser_output(&["-r", "--threads", "2", PCAP_FILE_PATH]);
+    let sorted = common::parser_output(&["-r", "--full-sort", PCAP_FILE_PATH]);
+
+    assert_eq!(streamed, sorted);
+}
+
+#[test]
+fn test_cli_streaming_reorder_reports_skew_violations() {
+    let output = common::parser_command(&["-r", "--max-skew", "0", PCAP_FILE_PATH]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("may be out of order"));
+
+    let mut streamed: Vec<&[u8]> = output.stdout.split(|&b| b == b'\n').collect();
+    let sorted = common::parser_output(&["-r", "--full-sort", PCAP_FILE_PATH]);
+    let mut sorted: Vec<&[u8]> = sorted.split(|&b| b == b'\n').collect();
+    streamed.sort_unstable();
+    sorted.sort_unstable();
+    assert_eq!(streamed, sorted, "late quotes must still be printed");
+}
+
+#[test]
+fn test_cli_streaming_reorder_prints_early_quotes_at_once() {
+    let mut pcap = common::Pcap::new(1);
+    for (i, (offset_us, frame)) in common::quote_frames(16_000, 1_500, 250).enumerate() {
+        if i == 10 {
+            let early = common::quote_payload("KR4101F30009", 30_000, "23595999");
+            pcap.packet(offset_us, &common::udp_frame(15516, &early));
+        }
+        pcap.packet(offset_us, &frame);
+    }
+    let path = pcap.write("early_quote.pcap");
+
+    let output = common::parser_command(&["-r", "--threads", "3", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(" 1 quotes arrived"));
+    let streamed = String::from_utf8(output.stdout).unwrap();
+    let early = streamed.lines().position(|l| l.contains(" 23:59:59.990 "));
+    assert!(early.is_some_and(|line| line < 4_000), "{early:?}");
+
+    // Every other row is released in accept-time order.
+    let sorted = common::run(&path, &["-r", "--full-sort"]);
+    let sorted = String::from_utf8(sorted).unwrap();
+    let (rest, last) = sorted.trim_end().rsplit_once('\n').unwrap();
+    assert!(last.contains(" 23:59:59.990 "));
+    let streamed: Vec<&str> = streamed.lines().filter(|l| *l != last).collect();
+    assert_eq!(streamed, rest.lines().collect::<Vec<_>>());
+}
+
+/// Roughly 4 MiB of quotes, so the windowed pipelines run over several
+/// windows on several workers. Accept times lag packet times by 0-2.5s.
+fn multi_window_capture() -> std::path::PathBuf {
+    let mut pcap = common::Pcap::new(1);
+    for (offset_us, frame) in common::quote_frames(16_000, 1_500, 250) {
+        pcap.packet(offset_us, &frame);
+    }
+    pcap.write("multi_window.pcap")
+}
+
+#[test]
+fn test_cli_windowed_pipelines_cover_every_quote_in_order() {
+    let path = multi_window_capture();
+    let path = path.to_str().expect("temp path should be UTF-8");
+
+    let default = String::from_utf8(common::parser_output(&["--threads", "3", path])).unwrap();
+    let packet_times: Vec<&str> = default.lines().map(|l| &l[..12]).collect();
+    assert_eq!(packet_times.len(), 16_000);
+    assert!(packet_times.windows(2).all(|w| w[0] <= w[1]));
+
+    let streamed = common::parser_output(&["-r", "--threads", "3", path]);
+    let sorted = common::parser_output(&["-r", "--full-sort", path]);
+    assert_eq!(streamed, sorted);
+}
+
+#[test]
+fn test_cli_strict_validation_prints_the_quotes_before_the_failure() {
+    let mut pcap = common::Pcap::new(1);
+    for (i, (offset_us, mut frame)) in common::quote_frames(16_000, 1_500, 250).enumerate() {
+        if i == 9_000 {
+            frame[42 + 207] = b'/'; // accept time
+        }
+        pcap.packet(offset_us, &frame);
+    }
+    let path = pcap.write("strict_multi_window.pcap");
+    let path = path.to_str().unwrap();
+
+    for threads in ["1", "4"] {
+        for ordering in [&[][..], &["-r"], &["-r", "--full-sort"]] {
+            let mut args = vec!["--validate", "strict", "--threads", threads];
+            args.extend_from_slice(ordering);
+            args.push(path);
+            let output = common::parser_command(&args);
+            assert_eq!(output.status.code(), Some(1), "{args:?}");
+            assert!(String::from_utf8_lossy(&output.stderr).contains("invalid accept time"));
+            let lines = String::from_utf8_lossy(&output.stdout).lines().count();
+            assert_eq!(lines, 9_000, "{args:?}");
+        }
+    }
+}
+
+#[test]
+fn test_cli_extended_output_prints_every_field() {
+    let payload = common::quote_payload("KR4101F30009", 25_000, "09153042");
+    let mut pcap = common::Pcap::new(1);
+    pcap.packet(0, &common::udp_frame(15516, &payload));
+    let path = pcap.write("cli_extended.pcap");
+
+    let out = common::parser_output(&["--extended", path.to_str().unwrap()]);
+    let bids = (0..5)
+        .rev()
+        .map(|i| format!("{:07}@{}/0001", i + 1, 25_000 - i));
+    let asks = (0..5).map(|i| format!("{:07}@{}/0001", i + 1, 25_001 + i));
+    let expected = ["09:00:00.000 09:15:30.420 KR4101F30009 001 00 0000015/00005".to_owned()]
+        .into_iter()
+        .chain(bids)
+        .chain(asks)
+        .chain(["0000015/00005\n".to_owned()])
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert_eq!(String::from_utf8_lossy(&out), expected);
+
+    // Same rows, in the same order, as the standard output.
+    let standard = common::parser_output(&["-r", PCAP_FILE_PATH]);
+    let extended = common::parser_output(&["-r", "--extended", PCAP_FILE_PATH]);
+    let extended = String::from_utf8_lossy(&extended);
+    let stripped: Vec<String> = extended
+        .lines()
+        .map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let levels = fields[6..16].iter().map(|l| l.split('/').next().unwrap());
+            fields[..3]
+                .iter()
+                .copied()
+                .chain(levels)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    assert_eq!(
+        stripped.join("\n") + "\n",
+        String::from_utf8_lossy(&standard)
+    );
+}
+
+#[test]
+fn test_cli_numeric_output_decodes_prices_and_quantities() {
+    let mut pcap = common::Pcap::new(1);
+    for (i, code) in ["KR4101F30009", "KR4201F32606"].iter().enumerate() {
+        let payload = common::quote_payload(code, 25_000, "09153042");
+        pcap.packet(i as u64 * 1_000, &common::udp_frame(15516, &payload));
+    }
+    let path = pcap.write("cli_numeric.pcap");
+    let path = path.to_str().unwrap();
+
+    let out = common::parser_output(&["--numeric", "--decimals", "KR42=1", path]);
+    let out = String::from_utf8_lossy(&out);
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[0].starts_with(
+        "09:00:00.000 09:15:30.420 KR4101F30009 5@249.96 4@249.97 3@249.98 2@249.99 1@250.00 \
+         1@250.01"
+    ));
+    assert!(lines[1].ends_with(" 1@2500.1 2@2500.2 3@2500.3 4@2500.4 5@2500.5"));
+
+    let extended = common::parser_output(&["--numeric", "--extended", "--decimals=0", path]);
+    assert!(String::from_utf8_lossy(&extended)
+        .starts_with("09:00:00.000 09:15:30.420 KR4101F30009 1 00 15/5 5@24996/1 "));
+
+    let bad = common::parser_command(&["--decimals", "KR42=10", path]);
+    assert_eq!(bad.status.code(), Some(2));
+}
+
+#[test]
+fn test_cli_issue_filter_keeps_matching_codes() {
+    let all = common::parser_output(&[PCAP_FILE_PATH]);
+    let all = String::from_utf8_lossy(&all);
+    let expected = |keep: &dyn Fn(&str) -> bool| {
+        all.lines()
+            .filter(|line| keep(line.split(' ').nth(2).unwrap()))
+            .map(|line| format!("{line}\n"))
+            .collect::<String>()
+    };
+
+    let out = common::parser_output(&["--issue", "KR4101F30009,KR43*", PCAP_FILE_PATH]);
+    let want = expected(&|code| code == "KR4101F30009" || code.starts_with("KR43"));
+    assert!(!want.is_empty());
+    assert_eq!(String::from_utf8_lossy(&out), want);
+
+    let list = common::write_temp("cli_issues.txt", b"# futures\nKR41?1F?000?\n");
+    let out =
+        common::parser_output(&["-r", "--issue-file", list.to_str().unwrap(), PCAP_FILE_PATH]);
+    let sorted = common::parser_output(&["-r", PCAP_FILE_PATH]);
+    let want = String::from_utf8_lossy(&sorted)
+        .lines()
+        .filter(|line| line.split(' ').nth(2).unwrap().starts_with("KR41"))
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+    assert_eq!(String::from_utf8_lossy(&out), want);
+
+    let output = common::parser_command(&["--issue-file", "no-such-list", PCAP_FILE_PATH]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no-such-list"));
+}
+
+#[test]
+fn test_cli_time_range_keeps_quotes_inside_it() {
+    let all = common::parser_output(&[PCAP_FILE_PATH]);
+    let all = String::from_utf8_lossy(&all);
+    let inside = |column: usize| {
+        all.lines()
+            .filter(|line| {
+                let time = line.split(' ').nth(column).unwrap();
+                ("09:00:05.5".."09:00:10").contains(&time)
+            })
+            .map(|line| format!("{line}\n"))
+            .collect::<String>()
+    };
+
+    let range = ["--from", "09:00:05.5", "--to", "09:00:10", PCAP_FILE_PATH];
+    let out = common::parser_output(&range);
+    assert!(!inside(0).is_empty());
+    assert_eq!(String::from_utf8_lossy(&out), inside(0));
+
+    let out = common::parser_output(&[&["--range-on", "accept"], &range[..]].concat());
+    assert_eq!(String::from_utf8_lossy(&out), inside(1));
+
+    let output = common::parser_command(&["--from", "09:00:10", "--to", "09:00", PCAP_FILE_PATH]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = common::parser_command(&["--from", "9:00", PCAP_FILE_PATH]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_cli_csv_output_has_a_column_per_field() {
+    let text = common::parser_output(&[PCAP_FILE_PATH]);
+    // Text lines list bids worst to best; CSV numbers levels from the best.
+    let expected: String = String::from_utf8_lossy(&text)
+        .lines()
+        .map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let level = |field: &str| {
+                let (quantity, price) = field.split_once('@').unwrap();
+                format!("{price};{quantity}")
+            };
+            let bids = fields[3..8].iter().rev().map(|f| level(f));
+            let asks = fields[8..13].iter().map(|f| level(f));
+            let row: Vec<String> = fields[..2]
+                .iter()
+                .map(|f| f.to_string())
+                .chain(["4".to_owned(), fields[2].to_owned()])
+                .chain(bids)
+                .chain(asks)
+                .collect();
+            format!("{}\n", row.join(";"))
+        })
+        .collect();
+
+    let csv = common::parser_output(&["--format", "csv", PCAP_FILE_PATH]);
+    let csv = String::from_utf8_lossy(&csv);
+    let (header, rows) = csv.split_once('\n').unwrap();
+    assert_eq!(header.split(',').count(), 24);
+    assert!(header
+        .starts_with("packet_time,accept_time,market_type,issue_code,bid_price_1,bid_quantity_1,"));
+    assert_eq!(rows.replace(',', ";"), expected);
+
+    let args = [
+        "--format=csv",
+        "--delimiter",
+        ";",
+        "--no-header",
+        PCAP_FILE_PATH,
+    ];
+    let csv = common::parser_output(&args);
+    assert_eq!(String::from_utf8_lossy(&csv), expected);
+
+    let csv = common::parser_output(&["--format", "csv", "--extended", PCAP_FILE_PATH]);
+    let csv = String::from_utf8_lossy(&csv);
+    assert!(csv.lines().all(|row| row.split(',').count() == 40));
+
+    for delimiter in ["x", "\"", ".", ""] {
+        let args = ["--format", "csv", "--delimiter", delimiter, PCAP_FILE_PATH];
+        let output = common::parser_command(&args);
+        assert_eq!(output.status.code(), Some(2), "{delimiter:?}");
+    }
+    for delimiter in [" ", "tab", "|"] {
+        let args = ["--format", "csv", "--delimiter", delimiter, PCAP_FILE_PATH];
+        assert!(
+            common::parser_command(&args).status.success(),
+            "{delimiter:?}"
+        );
+    }
+}
+
+#[test]
+fn test_cli_json_lines_output_is_typed() {
+    let text = common::parser_output(&["--numeric", PCAP_FILE_PATH]);
+    let text = String::from_utf8_lossy(&text);
+    let jsonl = common::parser_output(&["--format", "jsonl", "--numeric", PCAP_FILE_PATH]);
+    let jsonl = String::from_utf8_lossy(&jsonl);
+    assert_eq!(jsonl.lines().count(), text.lines().count());
+
+    for (line, json) in text.lines().zip(jsonl.lines()) {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let quote: serde_json::Value = serde_json::from_str(json).expect("valid JSON");
+        assert_eq!(
+            quote["packet_time"],
+            format!("2011-02-16T{}+09:00", fields[0])
+        );
+        assert_eq!(
+            quote["accept_time"],
+            format!("2011-02-16T{}+09:00", fields[1])
+        );
+        assert_eq!(quote["market_type"], "4");
+        assert_eq!(quote["issue_code"], fields[2]);
+        // Text lines list bids worst to best; JSON lists levels from the best.
+        let bids = fields[3..8].iter().rev();
+        let levels = bids.chain(&fields[8..13]);
+        let json_levels = quote["bids"].as_array().unwrap().iter();
+        let json_levels = json_levels.chain(quote["asks"].as_array().unwrap());
+        for (field, level) in levels.zip(json_levels) {
+            let (quantity, price) = field.split_once('@').unwrap();
+            assert_eq!(level["quantity"], quantity.parse::<u64>().unwrap());
+            assert_eq!(level["price"], price.parse::<f64>().unwrap());
+        }
+    }
+
+    // Fields that are not numbers come out as null; strings are escaped.
+    let mut payload = common::quote_payload("KR41\"01", 25_000, "09000000");
+    payload[30] = b'x';
+    let mut pcap = common::Pcap::new(1);
+    pcap.packet(0, &common::udp_frame(15516, &payload));
+    let path = pcap.write("cli_json_bad_field.pcap");
+    let args = ["--format", "jsonl", "--extended", path.to_str().unwrap()];
+    let out = common::parser_output(&args);
+    let quote: serde_json::Value = serde_json::from_slice(&out).expect("valid JSON");
+    assert_eq!(quote["issue_code"], "KR41\"01");
+    assert_eq!(quote["bids"][0]["price"], serde_json::Value::Null);
+    assert_eq!(quote["bids"][0]["quotes"], 1);
+}
@@ -0,0 +1,202 @@
+#![cfg(feature = "arrow")]
+
+mod common;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Int32Type, Int64Type, TimestampNanosecondType},
+    Array, RecordBatch,
+};
+use arrow_ipc::reader::{FileReader, StreamReader};
+use arrow_schema::DataType;
+use kopsi_200_pcap_parser::{quote_schema, QuoteReader, QuoteRecord, PCAP_FILE_PATH};
+#[cfg(feature = "parquet")]
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+#[cfg(feature = "parquet")]
+fn read_parquet(path: &std::path::Path) -> Vec<RecordBatch> {
+    let file = std::fs::File::open(path).expect("parquet file");
+    ParquetRecordBatchReaderBuilder::try_new(file)
+        .expect("parquet footer")
+        .build()
+        .expect("parquet reader")
+        .collect::<Result<_, _>>()
+        .expect("parquet batches")
+}
+
+/// `HH:MM:SS.fff` KST of nanoseconds since the epoch.
+#[cfg(feature = "parquet")]
+fn kst(ns: i64) -> String {
+    let ms = (ns / 1_000_000 + 9 * 3_600_000) % 86_400_000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet_export_has_every_field_as_a_column() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("quotes.parquet");
+    let path_str = path.to_str().unwrap();
+    common::parser_output(&["--format", "parquet", "-r", "-o", path_str, PCAP_FILE_PATH]);
+    let text = common::parser_output(&["--extended", "-r", "--numeric", PCAP_FILE_PATH]);
+    let text = String::from_utf8_lossy(&text);
+
+    let batches = read_parquet(&path);
+    let schema = batches[0].schema();
+    assert_eq!(schema.fields().len(), 41);
+    let issues = batches[0].column_by_name("issue_code").unwrap();
+    assert_eq!(issues.as_dictionary::<Int32Type>().values().len(), 4);
+
+    let mut rows = 0;
+    let mut lines = text.lines();
+    for batch in &batches {
+        let column = |name: &str| batch.column_by_name(name).unwrap();
+        let time = |name| {
+            column(name)
+                .as_primitive::<TimestampNanosecondType>()
+                .clone()
+        };
+        let int = |name| column(name).as_primitive::<Int64Type>().clone();
+        let (packet, accept) = (time("packet_time"), time("accept_time"));
+        let issue = issue_codes(column("issue_code"));
+        let (seq, bid_1, bid_qty_1, ask_5) = (
+            int("issue_seq_no"),
+            int("bid_price_1"),
+            int("bid_quantity_1"),
+            int("ask_price_5"),
+        );
+        for (row, issue) in issue.iter().enumerate() {
+            let fields: Vec<&str> = lines.next().unwrap().split(' ').collect();
+            assert_eq!(kst(packet.value(row)), fields[0]);
+            assert_eq!(kst(accept.value(row)), fields[1]);
+            assert_eq!(issue, fields[2]);
+            assert_eq!(seq.value(row).to_string(), fields[3]);
+            // The best bid is the last bid printed: `quantity@price/quotes`.
+            let best_bid = format!(
+                "{}@{:.2}",
+                bid_qty_1.value(row),
+                bid_1.value(row) as f64 / 100.0
+            );
+            assert!(fields[10].starts_with(&format!("{best_bid}/")));
+            let (_, worst_ask) = fields[15].split_once('@').unwrap();
+            assert!(worst_ask.starts_with(&format!("{:.2}/", ask_5.value(row) as f64 / 100.0)));
+        }
+        rows += batch.num_rows();
+    }
+    assert_eq!(rows, text.lines().count());
+}
+
+/// The issue code of each row of a dictionary column.
+fn issue_codes(column: &dyn Array) -> Vec<String> {
+    let dictionary = column.as_dictionary::<Int32Type>();
+    let values = dictionary.values().as_string::<i32>();
+    dictionary
+        .keys()
+        .iter()
+        .map(|key| values.value(key.unwrap() as usize).to_owned())
+        .collect()
+}
+
+#[test]
+fn test_record_batches_hold_every_quote_in_file_order() {
+    let records: Vec<QuoteRecord> = QuoteReader::open(PCAP_FILE_PATH)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let batches = QuoteReader::open(PCAP_FILE_PATH)
+        .unwrap()
+        .record_batches(1000)
+        .collect::<Result<Vec<_>, _>>()
+        .expect("fixture should parse");
+
+    let sizes: Vec<usize> = batches.iter().map(|batch| batch.num_rows()).collect();
+    assert_eq!(sizes, [1000, 1000, records.len() - 2000]);
+    assert!(batches.iter().all(|batch| batch.schema() == quote_schema()));
+
+    let mut records = records.iter();
+    for batch in &batches {
+        let packet = batch.column(0).as_primitive::<TimestampNanosecondType>();
+        let issue = issue_codes(batch.column_by_name("issue_code").unwrap());
+        let ask_1 = batch.column_by_name("ask_quantity_1").unwrap();
+        let ask_1 = ask_1.as_primitive::<Int64Type>();
+        for (row, issue) in issue.iter().enumerate() {
+            let record = records.next().unwrap();
+            let quote = record.quote();
+            let ns = record.ts_sec as i64 * 1_000_000_000 + record.ts_nsec as i64;
+            assert_eq!(packet.value(row), ns);
+            assert_eq!(issue.as_bytes(), quote.issue_code());
+            assert_eq!(
+                ask_1.value(row),
+                quote.ask(0).quantity_value().unwrap() as i64
+            );
+        }
+    }
+}
+
+#[test]
+fn test_arrow_ipc_file_and_stream_output() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let (file, stream) = (dir.join("quotes.arrow"), dir.join("quotes.arrows"));
+    // Enough rows for two batches, whose issue code dictionaries differ.
+    let inputs = vec![PCAP_FILE_PATH; 30];
+    let run = |format: &str, path: &std::path::Path| {
+        let args = [
+            &["--format", format, "-o", path.to_str().unwrap()],
+            &inputs[..],
+        ]
+        .concat();
+        common::parser_output(&args);
+    };
+    run("arrow", &file);
+    run("arrow-stream", &stream);
+
+    let expected: Vec<RecordBatch> = QuoteReader::open(PCAP_FILE_PATH)
+        .unwrap()
+        .record_batches(usize::MAX)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let codes = issue_codes(expected[0].column_by_name("issue_code").unwrap());
+    let issues: Vec<String> = (0..30).flat_map(|_| codes.clone()).collect();
+
+    let reader = FileReader::try_new(std::fs::File::open(&file).unwrap(), None).unwrap();
+    let schema = reader.schema();
+    assert_eq!(
+        schema.field_with_name("issue_code").unwrap().data_type(),
+        &DataType::Utf8
+    );
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(batches.len(), 2);
+    let read: Vec<String> = batches
+        .iter()
+        .flat_map(|batch| {
+            let column = batch
+                .column_by_name("issue_code")
+                .unwrap()
+                .as_string::<i32>();
+            column
+                .iter()
+                .map(|code| code.unwrap().to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(read, issues);
+
+    let reader = StreamReader::try_new(std::fs::File::open(&stream).unwrap(), None).unwrap();
+    assert_eq!(reader.schema(), quote_schema());
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    let read: Vec<String> = batches
+        .iter()
+        .flat_map(|batch| issue_codes(batch.column_by_name("issue_code").unwrap()))
+        .collect();
+    assert_eq!(read, issues);
+    let rows = expected[0].num_rows();
+    assert_eq!(
+        &*batches[0].column(10).slice(0, rows),
+        expected[0].column(10).as_ref()
+    );
+}
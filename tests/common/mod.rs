@@ -1,10 +1,17 @@
-use std::process::Command;
+#![allow(dead_code)]
 
-pub fn parser_output(args: &[&str]) -> Vec<u8> {
-    let output = Command::new(env!("CARGO_BIN_EXE_kopsi-200-pcap-parser"))
+use std::path::Path;
+use std::process::{Command, Output};
+
+pub fn parser_command(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_kopsi-200-pcap-parser"))
         .args(args)
         .output()
-        .expect("should run parser binary");
+        .expect("should run parser binary")
+}
+
+pub fn parser_output(args: &[&str]) -> Vec<u8> {
+    let output = parser_command(args);
 
     assert!(
         output.status.success(),
@@ -15,3 +22,316 @@ pub fn parser_output(args: &[&str]) -> Vec<u8> {
 
     output.stdout
 }
+
+/// `parser_output` on the capture at `path`, with `args` before it.
+pub fn run(path: &Path, args: &[&str]) -> Vec<u8> {
+    let mut all = args.to_vec();
+    all.push(path.to_str().expect("temp path should be UTF-8"));
+    parser_output(&all)
+}
+
+/// 2011-02-16 00:00:00 UTC, i.e. 09:00:00 KST on the fixture's trading day.
+pub const SESSION_START_UTC: u32 = 1_297_814_400;
+
+/// Builds a 215-byte B6034 payload. Level `i` of both sides gets price
+/// `base_price + i` and quantity `i + 1`; `accept` is `HHMMSSuu`.
+pub fn quote_payload(issue_code: &str, base_price: u32, accept: &str) -> Vec<u8> {
+    assert_eq!(accept.len(), 8);
+    let mut p = Vec::with_capacity(215);
+    p.extend_from_slice(b"B6034");
+    p.extend_from_slice(format!("{issue_code:<12}").as_bytes());
+    p.extend_from_slice(b"001"); // issue seq no
+    p.extend_from_slice(b"00"); // market status
+    p.extend_from_slice(b"0000015"); // total bid volume
+    for i in 0..5 {
+        p.extend_from_slice(format!("{:05}{:07}", base_price - i, i + 1).as_bytes());
+    }
+    p.extend_from_slice(b"0000015"); // total ask volume
+    for i in 0..5 {
+        p.extend_from_slice(format!("{:05}{:07}", base_price + 1 + i, i + 1).as_bytes());
+    }
+    p.extend_from_slice(b"0000500010001000100010001");
+    p.extend_from_slice(b"0000500010001000100010001");
+    p.extend_from_slice(accept.as_bytes());
+    p.push(0xff);
+    assert_eq!(p.len(), 215);
+    p
+}
+
+/// Wraps a UDP payload in Ethernet + IPv4 + UDP headers.
+pub fn udp_frame(dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut f = Vec::with_capacity(42 + payload.len());
+    f.extend_from_slice(&[0x01, 0x00, 0x5e, 0x00, 0x00, 0x01]);
+    f.extend_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+    f.extend_from_slice(&0x0800u16.to_be_bytes());
+    f.extend_from_slice(&ipv4_header(20 + 8 + payload.len(), 0, 0));
+    f.extend_from_slice(&udp_header(dst_port, payload.len()));
+    f.extend_from_slice(payload);
+    f
+}
+
+pub fn ipv4_header(total_len: usize, id: u16, frag: u16) -> [u8; 20] {
+    let mut h = [0u8; 20];
+    h[0] = 0x45;
+    h[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+    h[4..6].copy_from_slice(&id.to_be_bytes());
+    h[6..8].copy_from_slice(&frag.to_be_bytes());
+    h[8] = 64;
+    h[9] = 17;
+    h[12..16].copy_from_slice(&[10, 0, 0, 1]);
+    h[16..20].copy_from_slice(&[233, 37, 54, 1]);
+    h
+}
+
+pub fn udp_header(dst_port: u16, payload_len: usize) -> [u8; 8] {
+    let mut h = [0u8; 8];
+    h[0..2].copy_from_slice(&40_000u16.to_be_bytes());
+    h[2..4].copy_from_slice(&dst_port.to_be_bytes());
+    h[4..6].copy_from_slice(&((8 + payload_len) as u16).to_be_bytes());
+    h
+}
+
+/// Classic little-endian pcap writer with a configurable link type and
+/// timestamp magic.
+pub struct Pcap {
+    bytes: Vec<u8>,
+    nanos: bool,
+}
+
+impl Pcap {
+    pub fn new(linktype: u32) -> Self {
+        Self::with_magic(0xa1b2_c3d4, linktype)
+    }
+
+    pub fn with_magic(magic: u32, linktype: u32) -> Self {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&magic.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&65_535u32.to_le_bytes());
+        bytes.extend_from_slice(&linktype.to_le_bytes());
+        Self {
+            bytes,
+            nanos: magic == 0xa1b2_3c4d,
+        }
+    }
+
+    /// Appends a record `offset_us` microseconds after `SESSION_START_UTC`.
+    pub fn packet(&mut self, offset_us: u64, frame: &[u8]) -> &mut Self {
+        let sec = SESSION_START_UTC + (offset_us / 1_000_000) as u32;
+        let frac = (offset_us % 1_000_000) as u32;
+        let frac = if self.nanos { frac * 1000 } else { frac };
+        self.raw_packet(sec, frac, frame)
+    }
+
+    /// Appends a record with the timestamp fields exactly as given; `frac`
+    /// is in µs or ns depending on the magic.
+    pub fn raw_packet(&mut self, sec: u32, frac: u32, frame: &[u8]) -> &mut Self {
+        self.bytes.extend_from_slice(&sec.to_le_bytes());
+        self.bytes.extend_from_slice(&frac.to_le_bytes());
+        self.bytes
+            .extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.bytes
+            .extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.bytes.extend_from_slice(frame);
+        self
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Writes the capture under the integration-test temp dir.
+    pub fn write(&self, name: &str) -> std::path::PathBuf {
+        write_temp(name, &self.bytes)
+    }
+}
+
+pub fn write_temp(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, bytes).expect("should write temp capture");
+    path
+}
+
+/// Formats centiseconds since midnight as `HHMMSSuu`.
+pub fn hhmmssuu(cs: u64) -> String {
+    format!(
+        "{:02}{:02}{:02}{:02}",
+        cs / 360_000,
+        cs / 6_000 % 60,
+        cs / 100 % 60,
+        cs % 100
+    )
+}
+
+/// Accept time, `HHMMSSuu`, of a quote sent `offset_us` after
+/// `SESSION_START_UTC`: its packet time less a lag under `max_lag_cs` that
+/// `seed` scrambles, so accept order differs from packet order.
+pub fn lagged_accept(offset_us: u64, seed: u64, max_lag_cs: u64) -> String {
+    hhmmssuu(9 * 360_000 + offset_us / 10_000 - seed * 7919 % max_lag_cs)
+}
+
+/// `count` quote frames `spacing_us` apart, with their offsets from
+/// `SESSION_START_UTC`. Issue codes cycle through three futures and each
+/// quote's prices differ from its neighbours'.
+pub fn quote_frames(
+    count: u64,
+    spacing_us: u64,
+    max_lag_cs: u64,
+) -> impl Iterator<Item = (u64, Vec<u8>)> {
+    (0..count).map(move |i| {
+        let offset_us = i * spacing_us;
+        let code = ["KR4101F30009", "KR4101F60006", "KR4201F32606"][i as usize % 3];
+        let payload = quote_payload(
+            code,
+            20_000 + (i % 500) as u32,
+            &lagged_accept(offset_us, i, max_lag_cs),
+        );
+        (offset_us, udp_frame(15516, &payload))
+    })
+}
+
+/// Little-endian pcapng writer: one section, any number of interfaces.
+pub struct PcapNg {
+    bytes: Vec<u8>,
+    /// Timestamp units per second for each interface.
+    units: Vec<u64>,
+}
+
+impl PcapNg {
+    pub fn new() -> Self {
+        let mut ng = Self {
+            bytes: Vec::new(),
+            units: Vec::new(),
+        };
+        let mut body = Vec::new();
+        body.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        ng.block(0x0A0D_0D0A, &body);
+        ng
+    }
+
+    /// Adds an interface; `tsresol` is the raw `if_tsresol` option byte.
+    pub fn interface(&mut self, linktype: u16, tsresol: Option<u8>) -> &mut Self {
+        let mut body = Vec::new();
+        body.extend_from_slice(&linktype.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&65_535u32.to_le_bytes());
+        if let Some(r) = tsresol {
+            body.extend_from_slice(&9u16.to_le_bytes());
+            body.extend_from_slice(&1u16.to_le_bytes());
+            body.extend_from_slice(&[r, 0, 0, 0]);
+            body.extend_from_slice(&[0; 4]);
+        }
+        self.block(1, &body);
+        self.units.push(match tsresol {
+            None => 1_000_000,
+            Some(r) if r & 0x80 == 0 => 10u64.pow(r as u32),
+            Some(r) => 1u64 << (r & 0x7f),
+        });
+        self
+    }
+
+    /// Appends an Enhanced Packet Block `offset_us` after `SESSION_START_UTC`.
+    pub fn packet(&mut self, iface: u32, offset_us: u64, frame: &[u8]) -> &mut Self {
+        let units_per_sec = self.units[iface as usize];
+        let ts = SESSION_START_UTC as u64 * units_per_sec
+            + (offset_us as u128 * units_per_sec as u128 / 1_000_000) as u64;
+        let mut body = Vec::new();
+        body.extend_from_slice(&iface.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        body.extend_from_slice(frame);
+        body.resize(body.len().next_multiple_of(4), 0);
+        self.block(6, &body);
+        self
+    }
+
+    fn block(&mut self, block_type: u32, body: &[u8]) {
+        let len = (12 + body.len()) as u32;
+        self.bytes.extend_from_slice(&block_type.to_le_bytes());
+        self.bytes.extend_from_slice(&len.to_le_bytes());
+        self.bytes.extend_from_slice(body);
+        self.bytes.extend_from_slice(&len.to_le_bytes());
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn write(&self, name: &str) -> std::path::PathBuf {
+        write_temp(name, &self.bytes)
+    }
+}
+
+/// UDP header + payload.
+pub fn udp_segment(dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    [&udp_header(dst_port, payload.len())[..], payload].concat()
+}
+
+/// IPv4 header with `options_len` bytes of NOP options, then `l4`.
+pub fn ipv4_packet(options_len: usize, l4: &[u8]) -> Vec<u8> {
+    assert_eq!(options_len % 4, 0);
+    let mut h = ipv4_header(20 + options_len + l4.len(), 0, 0).to_vec();
+    h[0] = 0x40 | ((20 + options_len) / 4) as u8;
+    h.extend(std::iter::repeat_n(1u8, options_len));
+    h.extend_from_slice(l4);
+    h
+}
+
+/// IPv6 header, optionally followed by a hop-by-hop options header, then a
+/// UDP `l4`.
+pub fn ipv6_packet(hop_by_hop: bool, l4: &[u8]) -> Vec<u8> {
+    let ext: &[u8] = if hop_by_hop {
+        &[17, 0, 1, 4, 0, 0, 0, 0]
+    } else {
+        &[]
+    };
+    let mut h = vec![0x60, 0, 0, 0];
+    h.extend_from_slice(&((ext.len() + l4.len()) as u16).to_be_bytes());
+    h.push(if hop_by_hop { 0 } else { 17 });
+    h.push(64);
+    h.extend_from_slice(&[0xfe; 16]);
+    h.extend_from_slice(&[0xff; 16]);
+    h.extend_from_slice(ext);
+    h.extend_from_slice(l4);
+    h
+}
+
+/// Ethernet II header with the given VLAN tag protocol IDs, then `l3`.
+pub fn ethernet(tags: &[u16], ethertype: u16, l3: &[u8]) -> Vec<u8> {
+    let mut f = vec![
+        0x01, 0x00, 0x5e, 0x00, 0x00, 0x01, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55,
+    ];
+    for (i, tpid) in tags.iter().enumerate() {
+        f.extend_from_slice(&tpid.to_be_bytes());
+        f.extend_from_slice(&(100 + i as u16).to_be_bytes());
+    }
+    f.extend_from_slice(&ethertype.to_be_bytes());
+    f.extend_from_slice(l3);
+    f
+}
+
+/// Splits a UDP segment into Ethernet + IPv4 fragments carrying `chunk`
+/// bytes each (a multiple of 8), in order.
+pub fn ipv4_fragments(id: u16, l4: &[u8], chunk: usize) -> Vec<Vec<u8>> {
+    assert_eq!(chunk % 8, 0);
+    l4.chunks(chunk)
+        .enumerate()
+        .map(|(i, data)| {
+            let more = if (i + 1) * chunk < l4.len() {
+                0x2000
+            } else {
+                0
+            };
+            let frag = more | (i * chunk / 8) as u16;
+            let ip = [&ipv4_header(20 + data.len(), id, frag)[..], data].concat();
+            ethernet(&[], 0x0800, &ip)
+        })
+        .collect()
+}
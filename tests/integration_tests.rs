@@ -10,7 +10,7 @@ use std::io::BufReader;
 
 #[test]
 fn test_read_pcap_default_ordering_is_correct() {
-    let buf = common::parser_output(&[]);
+    let buf = common::parser_output(&[PCAP_FILE_PATH]);
     let output = String::from_utf8(buf).expect("output should be valid UTF-8");
 
     let packet_times: Vec<&str> = output
@@ -39,7 +39,7 @@ fn test_read_pcap_default_ordering_is_correct() {
 
 #[test]
 fn test_read_pcap_quote_accept_time_ordering_is_correct() {
-    let buf = common::parser_output(&["-r"]);
+    let buf = common::parser_output(&["-r", PCAP_FILE_PATH]);
     let output = String::from_utf8(buf).expect("output should be valid UTF-8");
 
     let quote_accept_times: Vec<&str> = output
@@ -249,7 +249,7 @@ fn reference_quote_accept_time_output() -> Vec<String> {
 //Test our performance parser against a simple parser and compare outputs
 #[test]
 fn test_has_file_been_parsed_and_sorted_correctly() {
-    let actual = String::from_utf8(common::parser_output(&["-r"]))
+    let actual = String::from_utf8(common::parser_output(&["-r", PCAP_FILE_PATH]))
         .expect("optimized output should be valid UTF-8");
     let actual_lines: Vec<&str> = actual.lines().collect();
     let expected = reference_quote_accept_time_output();
@@ -0,0 +1,129 @@
+mod common;
+
+use kopsi_200_pcap_parser::{
+    read_pcap_files, MessageDecoder, MessageRegistry, ParseOptions, PCAP_FILE_PATH,
+};
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+/// A 20-byte message with no end marker: header, issue code, 3 digits.
+struct Trade;
+
+impl MessageDecoder for Trade {
+    fn name(&self) -> &str {
+        "trade"
+    }
+
+    fn fixed_len(&self) -> Option<usize> {
+        Some(20)
+    }
+
+    fn write_fields(&self, message: &[u8], out: &mut Vec<u8>) -> bool {
+        if message.len() != 20 {
+            return false;
+        }
+        out.extend_from_slice(&message[5..17]);
+        out.push(b' ');
+        out.extend_from_slice(&message[17..20]);
+        true
+    }
+}
+
+/// Collects everything written to it.
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_every_message_type_is_printed() {
+    let out = common::parser_output(&["--messages", PCAP_FILE_PATH]);
+    let out = String::from_utf8_lossy(&out);
+    let quotes = common::parser_output(&["--extended", PCAP_FILE_PATH]);
+    let quotes = String::from_utf8_lossy(&quotes);
+
+    // Quotes print as in extended mode, with the type after the packet time.
+    let decoded: Vec<String> = out
+        .lines()
+        .filter_map(|line| line.split_once(" B6034 "))
+        .map(|(time, fields)| format!("{time} {fields}"))
+        .collect();
+    assert_eq!(decoded, quotes.lines().collect::<Vec<_>>());
+    assert!(out.lines().any(|line| line[13..].starts_with("G7034 ")));
+    assert!(out.lines().count() > decoded.len());
+
+    for flags in [
+        &["-r"][..],
+        &["--issue", "KR4101F30009"],
+        &["--market", "5"],
+        &["--from", "09:00:05", "--range-on", "accept"],
+    ] {
+        let output = common::parser_command(&[&["--messages"], flags, &[PCAP_FILE_PATH]].concat());
+        assert_eq!(output.status.code(), Some(2), "{flags:?}");
+    }
+
+    // A packet time range keeps the messages of its packets only.
+    let ranged = common::parser_output(&[
+        "--messages",
+        "--from",
+        "09:00:05",
+        "--to",
+        "09:00:06",
+        PCAP_FILE_PATH,
+    ]);
+    let ranged = String::from_utf8_lossy(&ranged);
+    let expected: Vec<&str> = out
+        .lines()
+        .filter(|line| ("09:00:05".."09:00:06").contains(&&line[..8]))
+        .collect();
+    assert!(!expected.is_empty() && expected.len() < out.lines().count());
+    assert_eq!(ranged.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn test_registered_decoders_split_and_print_messages() {
+    let quote = common::quote_payload("KR4101F30009", 25_000, "09000000");
+    let payload = [
+        &b"A3034KR4101F30009123"[..],
+        &quote,
+        b"A3034KR4101F30009456",
+        b"C1034??\xff",
+    ]
+    .concat();
+
+    let mut registry = MessageRegistry::new();
+    registry.register(*b"A3034", Trade);
+    let split: Vec<usize> = registry.messages(&payload).map(|(at, _)| at).collect();
+    // The quote has no decoder here, so it runs to its 0xFF.
+    assert_eq!(split, [0, 20, 235, 255]);
+    assert_eq!(registry.decoder(&payload).map(|d| d.name()), Some("trade"));
+    assert!(registry.decoder(&payload[255..]).is_none());
+    assert!(MessageRegistry::default().decoder(&quote).is_none());
+    assert!(MessageRegistry::kospi200().decoder(&quote).is_some());
+
+    let mut pcap = common::Pcap::new(1);
+    pcap.packet(0, &common::udp_frame(15516, &payload));
+    let path = pcap.write("messages_registered.pcap");
+
+    let out = Shared::default();
+    let options = ParseOptions {
+        messages: Some(Arc::new(registry)),
+        ..ParseOptions::default()
+    };
+    read_pcap_files(&[path], &options, out.clone()).expect("capture should parse");
+    assert_eq!(
+        String::from_utf8_lossy(&out.0.lock().unwrap()),
+        "09:00:00.000 A3034 KR4101F30009 123\n09:00:00.000 A3034 KR4101F30009 456\n"
+    );
+}
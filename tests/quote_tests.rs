@@ -0,0 +1,368 @@
+mod common;
+
+use kopsi_200_pcap_parser::{
+    parse_digits, payload_quotes, process_pcap_file, process_pcap_files, IssueFilter, Level,
+    MarketTypes, ParseError, ParseOptions, Price, PriceDecimals, Quote, QuoteField, QuoteReader,
+    QuoteRecord, QuoteSink, ScanMode, TimeField, TimeRange, Validation, LEVELS, PCAP_FILE_PATH,
+};
+use std::num::NonZeroUsize;
+
+#[test]
+fn test_quote_accessors_read_every_field() {
+    let payload = common::quote_payload("KR4101F30009", 25_000, "09153042");
+    let quote = Quote::new(&payload).expect("well-formed quote");
+
+    assert_eq!(quote.as_bytes(), &payload[..]);
+    assert_eq!(quote.issue_code(), b"KR4101F30009");
+    assert_eq!(quote.issue_seq_no(), b"001");
+    assert_eq!(quote.market_status(), b"00");
+    assert_eq!(quote.total_bid_volume(), b"0000015");
+    assert_eq!(quote.total_ask_volume(), b"0000015");
+    assert_eq!(
+        quote.bid(0),
+        Level {
+            price: b"25000",
+            quantity: b"0000001"
+        }
+    );
+    assert_eq!(quote.ask(4).price, b"25005");
+    assert_eq!(quote.bids().count(), LEVELS);
+    let bid_prices: Vec<&[u8]> = quote.bids().map(|l| l.price).collect();
+    assert_eq!(
+        bid_prices,
+        [b"25000", b"24999", b"24998", b"24997", b"24996"]
+    );
+    let ask_quantities: Vec<&[u8]> = quote.asks().map(|l| l.quantity).collect();
+    assert_eq!(ask_quantities[4], b"0000005");
+    assert_eq!(quote.bid_valid_quotes_total(), b"00005");
+    assert_eq!(quote.ask_valid_quotes(2), b"0001");
+    assert_eq!(quote.accept_time(), b"09153042");
+    assert_eq!(
+        quote.accept_time_cs(),
+        9 * 360_000 + 15 * 6_000 + 30 * 100 + 42
+    );
+}
+
+#[test]
+fn test_quote_rejects_other_messages() {
+    let payload = common::quote_payload("KR4101F30009", 25_000, "09000000");
+    assert!(Quote::new(&payload[..214]).is_none());
+    assert!(Quote::new(&[b"A3014", &payload[5..]].concat()).is_none());
+
+    let options = [b"B6035", &payload[5..]].concat();
+    assert_eq!(Quote::new(&options).unwrap().market_type(), b'5');
+    assert_eq!(
+        Quote::new(&payload).unwrap().market_type(),
+        MarketTypes::FUTURES
+    );
+}
+
+#[test]
+fn test_market_type_sets() {
+    let set = MarketTypes::of(*b"45");
+    assert!(set.contains(b'4') && set.contains(b'5') && !set.contains(b'6'));
+    assert_eq!(set.single(), None);
+    assert_eq!(MarketTypes::default().single(), Some(b'4'));
+    assert!((0..=u8::MAX).all(|t| MarketTypes::all().contains(t)));
+    assert_eq!(format!("{set:?}"), "MarketTypes(['4', '5'])");
+}
+
+#[test]
+fn test_issue_filter_matches_codes_prefixes_and_globs() {
+    let mut filter = IssueFilter::new();
+    assert!(filter.is_empty() && filter.matches(b"KR4101F30009"));
+
+    filter
+        .add("KR4101F30009")
+        .add("KR43*")
+        .add_lines("# options\n\n KR42?1*26?6 \n");
+    assert!(filter.matches(b"KR4101F30009"));
+    assert!(!filter.matches(b"KR4101F3000"));
+    assert!(!filter.matches(b"KR4101F60006"));
+    assert!(filter.matches(b"KR4301F32605"));
+    assert!(filter.matches(b"KR4201F32606"));
+    assert!(filter.matches(b"KR4211F32666"));
+    assert!(!filter.matches(b"KR4201F32605"));
+}
+
+#[test]
+fn test_prices_and_quantities_decode_to_integers() {
+    let payload = common::quote_payload("KR4201F32606", 255, "09153042");
+    let quote = Quote::new(&payload).unwrap();
+    let best_bid = quote.bid(0);
+
+    assert_eq!(best_bid.price_units(), Some(255));
+    assert_eq!(best_bid.quantity_value(), Some(1));
+    let decimals = PriceDecimals::default()
+        .with_prefix("KR4", 1)
+        .with_prefix("KR42", 3);
+    assert_eq!(decimals.for_issue(quote.issue_code()), 3);
+    assert_eq!(decimals.for_issue(b"KR4301F32606"), 1);
+    assert_eq!(decimals.for_issue(b"KR5101F30009"), 2);
+
+    let price = best_bid.scaled_price(3).unwrap();
+    assert_eq!(
+        price,
+        Price {
+            units: 255,
+            decimals: 3
+        }
+    );
+    assert_eq!(price.to_string(), "0.255");
+    assert_eq!(best_bid.scaled_price(0).unwrap().to_string(), "255");
+    assert_eq!(quote.ask(4).scaled_price(2).unwrap().to_f64(), 2.60);
+
+    assert_eq!(parse_digits(b"0001500"), Some(1500));
+    assert_eq!(parse_digits(b"00 1500"), None);
+    assert_eq!(parse_digits(&[b'9'; 25]), None);
+}
+
+#[test]
+fn test_quote_validate_names_the_failing_field() {
+    let valid = common::quote_payload("KR4101F30009", 25_000, "09153042");
+    assert_eq!(Quote::new(&valid).unwrap().validate(), Ok(()));
+
+    let corrupt = |at: usize, byte: u8| {
+        let mut payload = valid.clone();
+        payload[at] = byte;
+        payload
+    };
+    let failed = |payload: &[u8]| Quote::new(payload).unwrap().validate().unwrap_err();
+
+    let third_bid_price = failed(&corrupt(29 + 2 * 12 + 1, b' '));
+    assert_eq!(third_bid_price, QuoteField::BidPrice(2));
+    assert_eq!(third_bid_price.to_string(), "level 3 bid price");
+    assert_eq!(failed(&corrupt(96 + 5, b'x')), QuoteField::AskQuantity(0));
+    assert_eq!(failed(&corrupt(214, 0)), QuoteField::EndOfMessage);
+    assert_eq!(failed(&corrupt(206, b'3')), QuoteField::AcceptTime);
+
+    // Garbage digits give a garbage accept time, not a panic.
+    let garbled = corrupt(207, b'/');
+    assert_eq!(failed(&garbled), QuoteField::AcceptTime);
+    Quote::new(&garbled).unwrap().accept_time_cs();
+}
+
+#[test]
+fn test_validation_modes_skip_or_stop_at_invalid_quotes() {
+    // Used to panic (debug) or mis-sort (release) the accept-time modes.
+    let corrupt = common::quote_payload("KR4101F30009", 25_000, "0900a000");
+    let mut pcap = common::Pcap::new(1);
+    for (i, accept) in ["09000000", "09000100"].iter().enumerate() {
+        let quote = common::quote_payload("KR4101F30009", 25_000, accept);
+        pcap.packet(i as u64 * 20_000, &common::udp_frame(15516, &quote))
+            .packet(
+                i as u64 * 20_000 + 10_000,
+                &common::udp_frame(15516, &corrupt),
+            );
+    }
+    let path = pcap.write("quote_validation.pcap");
+    let path = path.to_str().unwrap();
+    let lines = |out: &[u8]| String::from_utf8_lossy(out).lines().count();
+
+    assert_eq!(lines(&common::parser_output(&["-r", path])), 4);
+
+    let lenient = common::parser_command(&["--validate", "lenient", "-r", path]);
+    assert!(lenient.status.success());
+    assert_eq!(lines(&lenient.stdout), 2);
+    assert!(String::from_utf8_lossy(&lenient.stderr).contains("skipped 2 invalid quotes"));
+
+    let report = common::parser_command(&["--validate=lenient", "--diagnostics", path]);
+    let report = String::from_utf8_lossy(&report.stderr);
+    assert!(report.contains("2  invalid accept time"), "{report}");
+
+    let strict = common::parser_command(&["--validate", "strict", path]);
+    assert_eq!(strict.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&strict.stderr).contains("invalid accept time"));
+
+    // The reader yields the quote before the invalid one, then the error.
+    let options = ParseOptions {
+        validation: Validation::Strict,
+        ..ParseOptions::default()
+    };
+    let mut reader = QuoteReader::with_options(path, &options).unwrap();
+    assert!(reader.next().unwrap().is_ok());
+    let err = reader.next().unwrap().unwrap_err();
+    let ParseError::File { source, .. } = err else {
+        panic!("expected a file error, got {err:?}");
+    };
+    assert!(matches!(
+        *source,
+        ParseError::InvalidField {
+            field: QuoteField::AcceptTime,
+            ..
+        }
+    ));
+    assert!(reader.next().is_none());
+
+    // So does the sorted reader.
+    let mut sorted = QuoteReader::with_options(path, &options).unwrap().sorted();
+    assert!(sorted.next().unwrap().is_ok());
+    assert!(sorted.next().unwrap().is_err());
+    assert!(sorted.next().is_none());
+}
+
+#[test]
+fn test_payload_quotes_splits_batched_messages() {
+    let first = common::quote_payload("KR4101F30009", 25_000, "09000000");
+    let second = common::quote_payload("KR4101F60006", 26_000, "09000001");
+    let payload = [&first[..], b"A30140000\xff", &second[..]].concat();
+
+    let codes: Vec<&[u8]> = payload_quotes(&payload).map(|q| q.issue_code()).collect();
+    assert_eq!(codes, [b"KR4101F30009", b"KR4101F60006"]);
+}
+
+/// `(accept time, issue code)` as printed, e.g. `09:00:00.000 KR4101F30009`.
+fn printed_keys(output: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(|l| l.split(' ').skip(1).take(2).collect::<Vec<_>>().join(" "))
+        .collect()
+}
+
+fn record_key(record: &QuoteRecord) -> String {
+    let quote = record.quote();
+    let t = quote.accept_time();
+    let code = String::from_utf8_lossy(quote.issue_code());
+    let t = String::from_utf8_lossy(t);
+    format!(
+        "{}:{}:{}.{}0 {code}",
+        &t[0..2],
+        &t[2..4],
+        &t[4..6],
+        &t[6..8]
+    )
+}
+
+#[test]
+fn test_quote_reader_yields_quotes_in_file_order() {
+    let records: Vec<QuoteRecord> = QuoteReader::open(PCAP_FILE_PATH)
+        .expect("fixture should open")
+        .collect::<Result<_, _>>()
+        .expect("fixture should parse");
+
+    assert!(records.windows(2).all(|w| w[0].offset < w[1].offset));
+    let keys: Vec<String> = records.iter().map(record_key).collect();
+    assert_eq!(
+        keys,
+        printed_keys(&common::parser_output(&[PCAP_FILE_PATH]))
+    );
+}
+
+#[test]
+fn test_time_ranges_skip_quotes_outside_them() {
+    // One quote every 10 ms from 09:00:00, accepted a second earlier; big
+    // enough to span several windows and bisection steps.
+    let mut pcap = common::Pcap::new(1);
+    for i in 0..6_000u64 {
+        let accept = common::hhmmssuu(9 * 360_000 + i - 100);
+        let payload = common::quote_payload("KR4101F30009", 25_000, &accept);
+        pcap.packet(i * 10_000, &common::udp_frame(15516, &payload));
+    }
+    let path = pcap.write("time_range.pcap");
+
+    let read = |field, from_cs: u32, to_cs: u32| {
+        let options = ParseOptions {
+            time_range: TimeRange {
+                field,
+                from_cs: 9 * 360_000 + from_cs,
+                to_cs: 9 * 360_000 + to_cs,
+            },
+            ..ParseOptions::default()
+        };
+        QuoteReader::with_options(&path, &options)
+            .expect("capture should open")
+            .map(|record| {
+                let record = record.expect("capture should parse");
+                let packet_cs = (record.ts_sec - common::SESSION_START_UTC) as u64 * 100
+                    + record.ts_nsec as u64 / 10_000_000;
+                (packet_cs, record.quote().accept_time_cs() as u64)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let packet = read(TimeField::Packet, 2_000, 2_050);
+    assert_eq!(packet.len(), 50);
+    assert_eq!(packet.first(), Some(&(2_000, 9 * 360_000 + 1_900)));
+    assert_eq!(packet.last().map(|&(p, _)| p), Some(2_049));
+
+    let accept = read(TimeField::Accept, 2_000, 2_050);
+    assert_eq!(accept.len(), 50);
+    assert_eq!(accept.first(), Some(&(2_100, 9 * 360_000 + 2_000)));
+
+    assert!(read(TimeField::Packet, 7_000, 8_000).is_empty());
+    assert_eq!(read(TimeField::Packet, 0, 10).len(), 10);
+    assert_eq!(read(TimeField::Packet, 5_990, 7_000).len(), 10);
+}
+
+#[test]
+fn test_sorted_quote_reader_matches_accept_time_output() {
+    let sorted: Vec<String> = QuoteReader::open(PCAP_FILE_PATH)
+        .expect("fixture should open")
+        .sorted()
+        .map(|r| record_key(&r.expect("fixture should parse")))
+        .collect();
+
+    let printed = common::parser_output(&["-r", "--full-sort", PCAP_FILE_PATH]);
+    assert_eq!(sorted, printed_keys(&printed));
+}
+
+#[test]
+fn test_quote_reader_reports_path_on_open_error() {
+    let err = QuoteReader::open("nonexistent.pcap")
+        .err()
+        .expect("should fail");
+    assert!(err.to_string().contains("nonexistent.pcap"));
+}
+
+/// Offsets and accept times of every quote, in the order seen.
+struct Collect;
+
+impl QuoteSink for Collect {
+    type State = Vec<(u64, u32)>;
+
+    fn new_state(&self) -> Self::State {
+        Vec::new()
+    }
+
+    fn visit(&self, seen: &mut Self::State, _: u32, _: u32, offset: u64, quote: Quote<'_>) {
+        seen.push((offset, quote.accept_time_cs()));
+    }
+
+    fn merge(&self, seen: &mut Self::State, later: Self::State) {
+        seen.extend(later);
+    }
+}
+
+#[test]
+fn test_quote_sink_sees_every_quote_once_in_file_order() {
+    let expected: Vec<(u64, u32)> = QuoteReader::open(PCAP_FILE_PATH)
+        .expect("fixture should open")
+        .map(|r| {
+            let r = r.expect("fixture should parse");
+            (r.offset, r.quote().accept_time_cs())
+        })
+        .collect();
+
+    for (threads, scan) in [
+        (1, ScanMode::Pattern),
+        (4, ScanMode::Pattern),
+        (3, ScanMode::Structural),
+    ] {
+        let options = ParseOptions {
+            threads: NonZeroUsize::new(threads),
+            scan,
+            ..ParseOptions::default()
+        };
+        let seen =
+            process_pcap_file(PCAP_FILE_PATH, &options, &Collect).expect("fixture should parse");
+        assert_eq!(seen, expected, "{threads} threads, {scan:?}");
+    }
+
+    let twice = process_pcap_files(
+        &[PCAP_FILE_PATH, PCAP_FILE_PATH],
+        &ParseOptions::default(),
+        &Collect,
+    )
+    .expect("fixture should parse");
+    assert_eq!(twice, [&expected[..], &expected[..]].concat());
+}
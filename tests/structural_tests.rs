@@ -0,0 +1,332 @@
+mod common;
+
+fn lines(out: &[u8]) -> usize {
+    String::from_utf8_lossy(out).lines().count()
+}
+
+/// Several MiB of quotes interleaved with other feed messages of varying
+/// sizes, so window starts land mid-record at arbitrary offsets.
+fn mixed_capture() -> std::path::PathBuf {
+    let mut pcap = common::Pcap::new(1);
+    for (i, (offset_us, quote)) in common::quote_frames(14_000, 2_000, 200).enumerate() {
+        if i % 3 == 1 {
+            let other = vec![b'7'; 40 + (i * 7919 % 900)];
+            pcap.packet(
+                offset_us,
+                &common::udp_frame(15515, &[b"A3014", &other[..]].concat()),
+            );
+            continue;
+        }
+        pcap.packet(offset_us, &quote);
+    }
+    pcap.write("structural_mixed.pcap")
+}
+
+#[test]
+fn test_structural_scan_matches_pattern_scan() {
+    let path = mixed_capture();
+
+    let pattern = common::run(&path, &["--threads", "4"]);
+    assert!(lines(&pattern) > 9_000);
+    assert_eq!(
+        common::run(&path, &["--structural", "--threads", "4"]),
+        pattern
+    );
+    assert_eq!(
+        common::run(&path, &["--structural", "--threads", "1"]),
+        pattern
+    );
+    assert_eq!(
+        common::run(&path, &["--structural", "-r", "--threads", "3"]),
+        common::run(&path, &["-r", "--full-sort"])
+    );
+}
+
+#[test]
+fn test_structural_scan_checks_destination_port() {
+    let quote = common::quote_payload("KR4101F30009", 25_000, "09000000");
+    let mut pcap = common::Pcap::new(1);
+    pcap.packet(10_000, &common::udp_frame(15515, &quote))
+        .packet(20_000, &common::udp_frame(9_999, &quote))
+        .packet(30_000, &common::udp_frame(15516, &quote));
+    let path = pcap.write("structural_ports.pcap");
+
+    // The pattern scan only checks the record length, so it takes the
+    // quote sent to an unrelated port as well.
+    assert_eq!(lines(&common::run(&path, &[])), 3);
+    assert_eq!(lines(&common::run(&path, &["--structural"])), 2);
+}
+
+#[test]
+fn test_variable_length_headers_are_decoded() {
+    let quote = |i: u32| common::quote_payload("KR4101F30009", 25_000 + i, "09000000");
+    let v4 =
+        |i: u32, opts: usize| common::ipv4_packet(opts, &common::udp_segment(15515, &quote(i)));
+    let v6 = |i: u32, hbh: bool| common::ipv6_packet(hbh, &common::udp_segment(15516, &quote(i)));
+    let frames = [
+        common::udp_frame(15515, &quote(0)),
+        common::ethernet(&[0x8100], 0x0800, &v4(1, 0)),
+        common::ethernet(&[0x88a8, 0x8100], 0x0800, &v4(2, 0)),
+        common::ethernet(&[], 0x0800, &v4(3, 12)),
+        common::ethernet(&[], 0x86dd, &v6(4, false)),
+        common::ethernet(&[0x8100], 0x86dd, &v6(5, true)),
+    ];
+
+    let mut pcap = common::Pcap::new(1);
+    for (i, frame) in frames.iter().enumerate() {
+        pcap.packet(i as u64 * 10_000, frame);
+    }
+    let path = pcap.write("structural_headers.pcap");
+
+    for args in [&[][..], &["--structural"][..]] {
+        let out = String::from_utf8(common::run(&path, args)).unwrap();
+        // Field 7 is the best bid, `<qty>@<price>`.
+        let bid1: Vec<&str> = out
+            .lines()
+            .map(|l| l.split(' ').nth(7).unwrap().split_once('@').unwrap().1)
+            .collect();
+        assert_eq!(
+            bid1,
+            ["25000", "25001", "25002", "25003", "25004", "25005"],
+            "{args:?}"
+        );
+    }
+}
+
+#[test]
+fn test_fragmented_quotes_are_reassembled() {
+    let segment = |i: u32| {
+        let quote = common::quote_payload("KR4101F30009", 25_000 + i, "09000000");
+        common::udp_segment(15515, &quote)
+    };
+    let a = common::ipv4_fragments(1, &segment(1), 64);
+    let b = common::ipv4_fragments(2, &segment(2), 96);
+    let lost = common::ipv4_fragments(3, &segment(3), 64);
+    // Interleaved and out of order; datagram 3 never gets its last piece.
+    let frames = [
+        &b[1], &a[0], &lost[0], &a[2], &b[0], &lost[1], &a[1], &a[3], &b[2],
+    ];
+
+    let mut pcap = common::Pcap::new(1);
+    for (i, frame) in frames.iter().enumerate() {
+        pcap.packet(i as u64 * 1_000, frame);
+    }
+    let path = pcap.write("structural_fragments.pcap");
+
+    let out = String::from_utf8(common::run(&path, &["--structural"])).unwrap();
+    let bid1: Vec<&str> = out
+        .lines()
+        .map(|l| l.split(' ').nth(7).unwrap().split_once('@').unwrap().1)
+        .collect();
+    // Each quote is stamped with the fragment that completed it.
+    assert_eq!(bid1, ["25001", "25002"]);
+    assert!(out.starts_with("09:00:00.007 "), "{out}");
+    assert_eq!(lines(&common::run(&path, &[])), 0);
+}
+
+#[test]
+fn test_fragmented_quotes_across_windows() {
+    let mut pcap = common::Pcap::new(1);
+    for i in 0..12_000u32 {
+        let quote = common::quote_payload("KR4101F30009", 20_000 + i % 900, "09000000");
+        let mut frags = common::ipv4_fragments(i as u16, &common::udp_segment(15515, &quote), 120);
+        if i % 2 == 0 {
+            frags.reverse();
+        }
+        for (j, frame) in frags.iter().enumerate() {
+            pcap.packet(i as u64 * 2_000 + j as u64, frame);
+        }
+    }
+    let path = pcap.write("structural_fragments_windows.pcap");
+
+    let single = common::run(&path, &["--structural", "--threads", "1"]);
+    assert_eq!(lines(&single), 12_000);
+    // Datagrams finished by one window's lookahead are not reported by the
+    // next window as incomplete.
+    let path_str = path.to_str().unwrap();
+    for threads in ["1", "4"] {
+        let output = common::parser_command(&[
+            "--structural",
+            "--diagnostics",
+            "--threads",
+            threads,
+            path_str,
+        ]);
+        let report = String::from_utf8_lossy(&output.stderr);
+        assert!(report.contains(": 0 records skipped"), "{report}");
+    }
+    assert_eq!(
+        common::run(&path, &["--structural", "--threads", "4"]),
+        single
+    );
+    assert_eq!(
+        common::run(&path, &["--structural", "-r", "--threads", "3"]),
+        common::run(
+            &path,
+            &["--structural", "-r", "--full-sort", "--threads", "3"]
+        )
+    );
+}
+
+/// A message of another type: header, filler, end-of-message marker.
+fn other_message(header: &[u8; 5], len: usize) -> Vec<u8> {
+    let mut m = header.to_vec();
+    m.resize(len - 1, b'0');
+    m.push(0xff);
+    m
+}
+
+#[test]
+fn test_every_quote_in_a_batched_payload_is_decoded() {
+    let quote = |i: u32| common::quote_payload("KR4101F30009", 25_000 + i, "09000000");
+    let batches = [
+        [quote(0), quote(1)].concat(),
+        [
+            other_message(b"A3014", 97),
+            quote(2),
+            quote(3),
+            other_message(b"C4014", 40),
+        ]
+        .concat(),
+        quote(4),
+        [other_message(b"A3014", 60), quote(5)].concat(),
+    ];
+    let mut pcap = common::Pcap::new(1);
+    for (i, payload) in batches.iter().enumerate() {
+        let ip = common::ipv4_packet(4 * (i % 2), &common::udp_segment(15515, payload));
+        pcap.packet(i as u64 * 10_000, &common::ethernet(&[], 0x0800, &ip));
+    }
+    let path = pcap.write("structural_batched.pcap");
+
+    for args in [&[][..], &["--structural"][..]] {
+        let out = String::from_utf8(common::run(&path, args)).unwrap();
+        let bid1: Vec<&str> = out
+            .lines()
+            .map(|l| l.split(' ').nth(7).unwrap().split_once('@').unwrap().1)
+            .collect();
+        assert_eq!(
+            bid1,
+            ["25000", "25001", "25002", "25003", "25004", "25005"],
+            "{args:?}"
+        );
+    }
+}
+
+#[test]
+fn test_batched_payloads_across_windows() {
+    let mut pcap = common::Pcap::new(1);
+    for i in 0..6_000u64 {
+        let quotes = (0..1 + i % 4).map(|j| {
+            common::quote_payload(
+                "KR4101F30009",
+                20_000 + (i % 900) as u32,
+                &common::lagged_accept(i * 2_000, i * 4 + j, 200),
+            )
+        });
+        let payload = [other_message(b"A3014", 30 + (i % 50) as usize)]
+            .into_iter()
+            .chain(quotes)
+            .collect::<Vec<_>>()
+            .concat();
+        pcap.packet(i * 2_000, &common::udp_frame(15516, &payload));
+    }
+    let path = pcap.write("structural_batched_windows.pcap");
+
+    let structural = common::run(&path, &["--structural", "--threads", "1"]);
+    assert_eq!(lines(&structural), 6_000 / 4 * (1 + 2 + 3 + 4));
+    assert_eq!(common::run(&path, &["--threads", "4"]), structural);
+    assert_eq!(
+        common::run(&path, &["-r", "--threads", "3"]),
+        common::run(&path, &["--structural", "-r", "--full-sort"])
+    );
+}
+
+#[test]
+fn test_diagnostics_report_skipped_records() {
+    let quote = common::quote_payload("KR4101F30009", 25_000, "09000000");
+    let mut pcap = common::Pcap::new(1);
+    pcap.packet(10_000, &common::udp_frame(15516, &quote))
+        .packet(20_000, &common::udp_frame(15516, &quote[..100]))
+        .packet(30_000, &common::udp_frame(15516, &quote));
+    // A final record cut short by the end of the file.
+    let mut bytes = pcap.bytes().to_vec();
+    bytes.truncate(bytes.len() - 50);
+    let path = common::write_temp("structural_diagnostics.pcap", &bytes);
+
+    let output = common::parser_command(&[
+        "--structural",
+        "--diagnostics",
+        path.to_str().expect("temp path should be UTF-8"),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(lines(&output.stdout), 1);
+    let report = String::from_utf8_lossy(&output.stderr);
+    assert!(report.contains("2 records skipped"), "{report}");
+    assert!(report.contains("1  bad quote length"), "{report}");
+    assert!(report.contains("1  truncated record"), "{report}");
+    assert!(report.contains("structural_diagnostics.pcap: "), "{report}");
+
+    let quiet = common::parser_command(&["--structural", path.to_str().unwrap()]);
+    assert!(quiet.stderr.is_empty());
+}
+
+#[test]
+fn test_market_types_are_filtered_in_both_scans() {
+    let quote = |market: u8, code: &str| {
+        let mut payload = common::quote_payload(code, 25_000, "09000000");
+        payload[4] = market;
+        payload
+    };
+    let mut pcap = common::Pcap::new(1);
+    pcap.packet(
+        10_000,
+        &common::udp_frame(15516, &quote(b'4', "KR4101F30009")),
+    )
+    .packet(
+        20_000,
+        &common::udp_frame(15516, &quote(b'5', "KR4201F32606")),
+    )
+    .packet(
+        30_000,
+        &common::udp_frame(
+            15516,
+            &[quote(b'6', "KR4301F32606"), quote(b'4', "KR4101F60006")].concat(),
+        ),
+    );
+    let path = pcap.write("structural_markets.pcap");
+
+    for scan in [&[][..], &["--structural"]] {
+        let codes = |market: &str| {
+            let out = common::run(&path, &[scan, &["--market", market]].concat());
+            String::from_utf8_lossy(&out)
+                .lines()
+                .map(|line| {
+                    line.split(' ')
+                        .skip(2)
+                        .take(2)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+        };
+        let default = String::from_utf8_lossy(&common::run(&path, scan)).into_owned();
+        assert_eq!(lines(default.as_bytes()), 2, "{scan:?}");
+        // A single market type is not printed.
+        assert_eq!(codes("5"), ["KR4201F32606 0000005@24996"], "{scan:?}");
+        assert_eq!(
+            codes("all"),
+            [
+                "4 KR4101F30009",
+                "5 KR4201F32606",
+                "6 KR4301F32606",
+                "4 KR4101F60006"
+            ],
+            "{scan:?}"
+        );
+        assert_eq!(
+            codes("6,5"),
+            ["5 KR4201F32606", "6 KR4301F32606"],
+            "{scan:?}"
+        );
+    }
+}